@@ -0,0 +1,177 @@
+/// Characters of lz-string's base64 output, `=` is padding
+const BASE64_CHARS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/=";
+/// Characters of lz-string's output for URI components
+const URI_CHARS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+-$";
+
+/// Decompress the profile's card data with the compression scheme it's exported with.
+/// Schemes are none (empty), `lz-string` for lz-string's base64 and `lz-string-uri`
+/// for lz-string's URI component output. These names are our own, the name Bestdori
+/// gives its scheme is unverified. Any other name is an unknown scheme.
+pub fn decompress(compression: &str, data: &str) -> Result<String, String> {
+    match compression {
+        "" => Ok(data.to_string()),
+        "lz-string" => {
+            lz_decompress_with(BASE64_CHARS, data).ok_or(format!("Invalid {} data", compression))
+        }
+        "lz-string-uri" => {
+            lz_decompress_with(URI_CHARS, data).ok_or(format!("Invalid {} data", compression))
+        }
+        _ => Err(format!("unknown compression {}", compression)),
+    }
+}
+
+/// lz-string's decompression of data written with the characters in `chars`
+fn lz_decompress_with(chars: &str, data: &str) -> Option<String> {
+    // URI components may have turned `+` into a space
+    let values = data
+        .chars()
+        .map(|c| if c == ' ' { '+' } else { c })
+        .map(|c| chars.find(c).map(|value| value as u32))
+        .collect::<Option<Vec<u32>>>()?;
+    lz_decompress(&values, 32)
+}
+
+/// Reads the bits of lz-string's values, highest bit of each value first
+struct BitReader<'a> {
+    values: &'a [u32],
+    value: u32,
+    position: u32,
+    reset_value: u32,
+    index: usize,
+}
+
+impl BitReader<'_> {
+    /// Read `count` bits into a number, lowest bit first
+    fn read_bits(&mut self, count: u32) -> u32 {
+        let mut bits = 0;
+        for power in 0..count {
+            if self.value & self.position > 0 {
+                bits |= 1 << power;
+            }
+            self.position >>= 1;
+            if self.position == 0 {
+                self.position = self.reset_value;
+                self.value = self.values.get(self.index).copied().unwrap_or(0);
+                self.index += 1;
+            }
+        }
+        bits
+    }
+}
+
+/// lz-string's decompression, `reset_value` is the highest bit of a value
+fn lz_decompress(values: &[u32], reset_value: u32) -> Option<String> {
+    if values.is_empty() {
+        return Some(String::new());
+    }
+    let mut reader = BitReader {
+        values,
+        value: values[0],
+        position: reset_value,
+        reset_value,
+        index: 1,
+    };
+    // The first three entries are the codes of a new 8 bit, a new 16 bit and the end
+    let mut dictionary: Vec<Vec<u16>> = vec![Vec::new(); 3];
+    let mut enlarge_in: u32 = 4;
+    let mut num_bits: u32 = 3;
+    let first = match reader.read_bits(2) {
+        0 => reader.read_bits(8) as u16,
+        1 => reader.read_bits(16) as u16,
+        _ => return Some(String::new()),
+    };
+    dictionary.push(vec![first]);
+    let mut word = vec![first];
+    let mut result = vec![first];
+    loop {
+        // Data ended without the end code
+        if reader.index > values.len() {
+            return None;
+        }
+        let mut code = reader.read_bits(num_bits) as usize;
+        match code {
+            0 | 1 => {
+                let char_bits = if code == 0 { 8 } else { 16 };
+                dictionary.push(vec![reader.read_bits(char_bits) as u16]);
+                code = dictionary.len() - 1;
+                enlarge_in -= 1;
+            }
+            2 => return String::from_utf16(&result).ok(),
+            _ => {}
+        }
+        if enlarge_in == 0 {
+            enlarge_in = 1 << num_bits;
+            num_bits += 1;
+        }
+        let entry = if code < dictionary.len() {
+            dictionary[code].clone()
+        } else if code == dictionary.len() {
+            let mut entry = word.clone();
+            entry.push(word[0]);
+            entry
+        } else {
+            return None;
+        };
+        result.extend_from_slice(&entry);
+        let mut new_word = word;
+        new_word.push(entry[0]);
+        dictionary.push(new_word);
+        enlarge_in -= 1;
+        word = entry;
+        if enlarge_in == 0 {
+            enlarge_in = 1 << num_bits;
+            num_bits += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user_data::{RawUserProfile, UserProfile};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    #[test]
+    fn decompress_test() {
+        assert_eq!(
+            decompress("lz-string", "BIUwNmD2A0AEDukBOYAmQ===").unwrap(),
+            "Hello, world"
+        );
+        assert_eq!(decompress("", "AhB0").unwrap(), "AhB0");
+        assert!(decompress("lz-string", "#").is_err());
+        assert!(decompress("gzip", "MA_G1").is_err());
+        // Five card entries, compressed with lz-string's compressToBase64 and
+        // compressToEncodedURIComponent
+        let plain = "AhB0s7IU129CN1g9D81s5-o0Y";
+        let base64 = "IICwQgDAzg7AkgVQIwCYCcBhAckg5mgEQA4koBWAWgHsIBNIA===";
+        let uri = "IICwQgDAzg7AkgVQIwCYCcBhAckg5mgEQA4koBWAWgHsIBNIA";
+        assert_eq!(decompress("lz-string", base64).unwrap(), plain);
+        assert_eq!(decompress("lz-string-uri", uri).unwrap(), plain);
+        // Scheme names we don't know are errors, even for data lz-string loads
+        assert!(decompress("lzstring", uri).is_err());
+        assert_eq!(
+            decompress("deflate", base64),
+            Err(String::from("unknown compression deflate"))
+        );
+        assert!(decompress("base64", "").is_err());
+        // The compressed export loads the same cards as the plain one
+        let profile = |compression: &str, data: &str| {
+            let raw: RawUserProfile = serde_json::from_value(json!({
+                "name": "tester",
+                "server": 0,
+                "compression": compression,
+                "data": data,
+                "items": {},
+            }))
+            .unwrap();
+            UserProfile::new(&raw, &HashMap::new())
+                .card_status
+                .iter()
+                .map(|it| (it.id, it.level, it.skill))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(profile("", plain).len(), 5);
+        assert_eq!(profile("", plain), profile("lz-string", base64));
+    }
+}
@@ -0,0 +1,262 @@
+use serde::Deserialize;
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+
+use crate::user_data::*;
+use crate::{
+    best_of_choices_with, item_choices, profile_skill_rates, CalcCard, CalcResult, EventType,
+};
+
+/// Constraints on the team, every field can be left out
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct TeamConstraints {
+    /// Cards that must be on the team
+    pub pinned_cards: Vec<u32>,
+    /// Cards that can't be on the team
+    pub banned_cards: Vec<u32>,
+    /// Characters that can't be on the team
+    pub banned_characters: Vec<u8>,
+    /// Band id that every member must belong to
+    pub band: Option<u8>,
+    /// Attribute that every member must have, such as happy, cool
+    pub attribute: Option<Attribute>,
+    pub min_rarity: Option<u8>,
+    pub max_rarity: Option<u8>,
+    /// Allowed card types, such as permanent, limited, dreamfes, birthday.
+    /// Empty means every type.
+    pub card_types: Vec<String>,
+}
+
+impl TeamConstraints {
+    /// Why the card can't be on the team, `None` if it can
+    fn reject_reason(
+        &self,
+        card_id: u32,
+        card: &Card,
+        character_band: &HashMap<u8, u8>,
+    ) -> Option<String> {
+        if self.banned_cards.contains(&card_id) {
+            return Some(format!("card {} is banned", card_id));
+        }
+        if self.banned_characters.contains(&card.character_id) {
+            return Some(format!("character {} is banned", card.character_id));
+        }
+        if let Some(band) = &self.band {
+            if character_band.get(&card.character_id) != Some(band) {
+                return Some(format!("card {} is not in band {}", card_id, band));
+            }
+        }
+        if let Some(attribute) = self.attribute {
+            if card.attribute != attribute {
+                return Some(format!("card {} is not {}", card_id, attribute.name()));
+            }
+        }
+        if self.min_rarity.is_some_and(|rarity| card.rarity < rarity)
+            || self.max_rarity.is_some_and(|rarity| card.rarity > rarity)
+        {
+            return Some(format!("card {} rarity is out of range", card_id));
+        }
+        if !self.card_types.is_empty() && !self.card_types.contains(&card.type_) {
+            return Some(format!(
+                "card {} type {} is not allowed",
+                card_id, card.type_
+            ));
+        }
+        None
+    }
+
+    /// Reasons why the pinned cards can't be put on the team
+    fn pinned_conflicts(
+        &self,
+        cards: &HashMap<String, Card>,
+        user_profile: &UserProfile,
+        character_band: &HashMap<u8, u8>,
+    ) -> Vec<String> {
+        let mut reasons: Vec<String> = Vec::new();
+        if self.pinned_cards.len() > 5 {
+            reasons.push(format!("{} cards are pinned", self.pinned_cards.len()));
+        }
+        let mut characters: HashSet<u8> = HashSet::new();
+        for card_id in self.pinned_cards.iter() {
+            let card = match cards.get(&card_id.to_string()) {
+                Some(card) => card,
+                None => {
+                    reasons.push(format!("card {} not found", card_id));
+                    continue;
+                }
+            };
+            let usable = user_profile.card_status.iter().any(|it| {
+                it.id == *card_id
+                    && !it.exclude
+                    && card.released_by(user_profile.server, user_profile.as_of)
+            });
+            if !usable {
+                reasons.push(format!("card {} is not usable in the profile", card_id));
+            }
+            if let Some(reason) = self.reject_reason(*card_id, card, character_band) {
+                reasons.push(reason);
+            }
+            if !characters.insert(card.character_id) {
+                reasons.push(format!("character {} is pinned twice", card.character_id));
+            }
+        }
+        reasons
+    }
+}
+
+/// Pick the best five cards with different characters from sorted cards,
+/// starting with the pinned cards and skipping the rejected ones
+pub fn pick_constrained_team(
+    calc_cards: &[CalcCard],
+    pinned_cards: &[u32],
+    allowed: &HashSet<u32>,
+) -> (HashMap<u8, CalcCard>, u32) {
+    let mut result: HashMap<u8, CalcCard> = HashMap::new();
+    let mut result_score = 0;
+    let pinned = calc_cards
+        .iter()
+        .filter(|it| pinned_cards.contains(&it.card_id));
+    let others = calc_cards.iter().filter(|it| allowed.contains(&it.card_id));
+    for it in pinned.chain(others) {
+        if result.len() >= 5 {
+            break;
+        }
+        if let Entry::Vacant(entry) = result.entry(it.character_id) {
+            result_score += (it.score as f64 * it.skill_mul * it.bp_mul) as u32;
+            entry.insert(*it);
+        }
+    }
+    (result, result_score)
+}
+
+/// Ids of the profile's cards that the constraints don't reject
+pub fn allowed_cards(
+    cards: &HashMap<String, Card>,
+    user_profile: &UserProfile,
+    character_band: &HashMap<u8, u8>,
+    constraints: &TeamConstraints,
+) -> HashSet<u32> {
+    user_profile
+        .card_status
+        .iter()
+        .filter(|it| {
+            cards.get(&it.id.to_string()).is_some_and(|card| {
+                constraints
+                    .reject_reason(it.id, card, character_band)
+                    .is_none()
+            })
+        })
+        .map(|it| it.id)
+        .collect()
+}
+
+/// Use user profile and event bonus to calculate max score cardset under the constraints.
+/// Returns every reason found when the constraints can't be satisfied.
+#[allow(clippy::too_many_arguments)]
+pub fn calc_constrained_score(
+    cards: &HashMap<String, Card>,
+    user_profile: &UserProfile,
+    event_bonus: &EventBonus,
+    character_band: &HashMap<u8, u8>,
+    song_data: &[SongNote],
+    song_level: u32,
+    skills: &HashMap<String, Skill>,
+    event_type: EventType,
+    constraints: &TeamConstraints,
+) -> Result<CalcResult, String> {
+    let reasons = constraints.pinned_conflicts(cards, user_profile, character_band);
+    if !reasons.is_empty() {
+        return Err(reasons.join("; "));
+    }
+    let allowed = allowed_cards(cards, user_profile, character_band, constraints);
+    let skill_rates = profile_skill_rates(
+        cards,
+        user_profile,
+        song_data,
+        song_level,
+        skills,
+        event_type,
+    );
+    let choices = item_choices(
+        cards,
+        user_profile,
+        event_bonus,
+        character_band,
+        event_type,
+        &skill_rates,
+    );
+    let result = best_of_choices_with(&choices, event_bonus, character_band, |calc_cards| {
+        pick_constrained_team(calc_cards, &constraints.pinned_cards, &allowed)
+    });
+    if result.best_team.len() < 5 {
+        return Err(format!(
+            "only {} different characters satisfy the constraints",
+            result.best_team.len()
+        ));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_json::*;
+
+    #[test]
+    fn constraints_test() {
+        let Fixture {
+            cards: all_cards,
+            user_profile,
+            character_band,
+            song_notes,
+            skills,
+            ..
+        } = read_fixture();
+        let event_bonus = summer_vacation_bonus();
+        let calc = |constraints: &TeamConstraints| {
+            calc_constrained_score(
+                &all_cards,
+                &user_profile,
+                &event_bonus,
+                &character_band,
+                &song_notes,
+                26,
+                &skills,
+                EventType::Free,
+                constraints,
+            )
+        };
+        let pinned = user_profile
+            .card_status
+            .iter()
+            .filter(|it| !it.exclude)
+            .map(|it| (it.id, &all_cards[&it.id.to_string()]))
+            .find(|(_, card)| card.rarity >= 3 && card.character_id != 16)
+            .unwrap()
+            .0;
+        let constraints = TeamConstraints {
+            pinned_cards: vec![pinned],
+            banned_characters: vec![16],
+            min_rarity: Some(3),
+            ..Default::default()
+        };
+        let result = calc(&constraints).unwrap();
+        assert!(result.best_team.values().any(|it| it.card_id == pinned));
+        assert!(!result.best_team.contains_key(&16));
+        // A pinned card can't be banned at the same time
+        let constraints = TeamConstraints {
+            pinned_cards: vec![pinned],
+            banned_cards: vec![pinned],
+            ..Default::default()
+        };
+        assert!(calc(&constraints).is_err());
+        // Five members can't come from a single band and be banned from it
+        let band = character_band[&16];
+        let constraints = TeamConstraints {
+            band: Some(band),
+            banned_characters: vec![16],
+            ..Default::default()
+        };
+        assert!(calc(&constraints).is_err());
+    }
+}
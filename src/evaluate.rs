@@ -0,0 +1,234 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::song_calculate::{skill_rate_table, team_song_score};
+use crate::user_data::*;
+use crate::{
+    apply_objective, area_items_of, calc_card_with_items, calc_max_score, skill_tag, CalcCard,
+    EventType,
+};
+
+/// Area items chosen for the team, the same as the ones in `CalcResult`
+#[derive(Deserialize)]
+pub struct ItemSelection {
+    pub band_id: u8,
+    /// Whether the items for every band take the shared slots of the band's items
+    #[serde(default)]
+    pub everyone: bool,
+    pub magazine: Parameter,
+    pub prop: Attribute,
+}
+
+/// Score of a given team, broken down the same way as the optimizer's
+#[derive(Serialize)]
+pub struct TeamEvaluation {
+    /// Cards in slot order
+    cards: Vec<CalcCard>,
+    /// Team score that the optimizer maximizes, comparable with `CalcResult`'s score
+    score: u32,
+    /// Expected score of the team in the song
+    song_score: f64,
+    /// Card id of the leader that gives the expected score
+    leader: u32,
+    /// Score of the optimizer's best team
+    best_score: u32,
+    /// Score lost versus the best team
+    gap: i64,
+}
+
+/// Score a team of five cards in slot order with the chosen area items,
+/// without searching for a better team
+#[allow(clippy::too_many_arguments)]
+pub fn evaluate_team(
+    cards: &HashMap<String, Card>,
+    user_profile: &UserProfile,
+    event_bonus: &EventBonus,
+    character_band: &HashMap<u8, u8>,
+    song_data: &[SongNote],
+    song_level: u32,
+    skills: &HashMap<String, Skill>,
+    event_type: EventType,
+    card_ids: &[u32],
+    selection: &ItemSelection,
+) -> Result<TeamEvaluation, String> {
+    if card_ids.len() != 5 {
+        return Err(format!("A team has 5 cards, {} are given", card_ids.len()));
+    }
+    let items = area_items_of(user_profile)
+        .into_iter()
+        .find(|it| {
+            it.band_id == selection.band_id
+                && it.everyone_bonus.is_empty() != selection.everyone
+                && it.magazine_name == selection.magazine
+                && it.prop_name == selection.prop
+        })
+        .ok_or(format!("Band {} has no items", selection.band_id))?;
+    let mut team: Vec<CalcCard> = Vec::new();
+    let mut characters: HashSet<u8> = HashSet::new();
+    for card_id in card_ids.iter() {
+        let card = cards
+            .get(&card_id.to_string())
+            .ok_or(format!("Card {} not found", card_id))?;
+        let card_stat = user_profile
+            .card_status
+            .iter()
+            .find(|it| it.id == *card_id)
+            .ok_or(format!("Card {} is not in the profile", card_id))?;
+        if !characters.insert(card.character_id) {
+            return Err(format!(
+                "Character {} is in the team twice",
+                card.character_id
+            ));
+        }
+        team.push(calc_card_with_items(
+            card,
+            card_stat,
+            event_bonus,
+            character_band,
+            &items,
+        ));
+    }
+
+    let (_, skill_weight) = event_type.objective();
+    let skill_rates = if skill_weight > 0.0 {
+        let mut calc_skills: Vec<u32> = team
+            .iter()
+            .map(|it| skill_tag(it.skill_id, it.skill_level))
+            .collect();
+        calc_skills.sort();
+        calc_skills.dedup();
+        skill_rate_table(&calc_skills, skills, song_data, song_level, 0.97, false)
+    } else {
+        HashMap::new()
+    };
+    for it in team.iter_mut() {
+        apply_objective(it, event_type, &skill_rates);
+    }
+    let score: u32 = team
+        .iter()
+        .map(|it| (it.score as f64 * it.skill_mul * it.bp_mul) as u32)
+        .sum();
+
+    let power: u32 = team.iter().map(|it| it.power).sum();
+    let skill_ids: Vec<u8> = team.iter().map(|it| it.skill_id).collect();
+    let skill_levels: Vec<u8> = team.iter().map(|it| it.skill_level).collect();
+    let (song_score, leader) = team_song_score(
+        power,
+        &skill_ids,
+        &skill_levels,
+        song_level,
+        false,
+        0.97,
+        song_data,
+        skills,
+    );
+    let best_score = calc_max_score(
+        cards,
+        user_profile,
+        event_bonus,
+        character_band,
+        song_data,
+        song_level,
+        skills,
+        event_type,
+    )
+    .score;
+    Ok(TeamEvaluation {
+        leader: team[leader].card_id,
+        cards: team,
+        score,
+        song_score,
+        best_score,
+        gap: best_score as i64 - score as i64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_json::*;
+
+    #[test]
+    fn evaluate_test() {
+        let Fixture {
+            cards: all_cards,
+            user_profile,
+            character_band,
+            song_notes,
+            skills,
+            ..
+        } = read_fixture();
+        let event_bonus = summer_vacation_bonus();
+        let evaluate = |card_ids: &[u32], selection: &ItemSelection| {
+            evaluate_team(
+                &all_cards,
+                &user_profile,
+                &event_bonus,
+                &character_band,
+                &song_notes,
+                26,
+                &skills,
+                EventType::Free,
+                card_ids,
+                selection,
+            )
+        };
+        let best = calc_max_score(
+            &all_cards,
+            &user_profile,
+            &event_bonus,
+            &character_band,
+            &song_notes,
+            26,
+            &skills,
+            EventType::Free,
+        );
+        // The optimizer's own team has no gap
+        let card_ids: Vec<u32> = best.best_team.values().map(|it| it.card_id).collect();
+        let selection = ItemSelection {
+            band_id: best.band_id,
+            everyone: best.everyone,
+            magazine: best.magazine.unwrap(),
+            prop: best.prop.unwrap(),
+        };
+        let evaluation = evaluate(&card_ids, &selection).unwrap();
+        assert_eq!(evaluation.gap, 0);
+        assert!(evaluation.song_score > 0.0);
+        assert!(card_ids.contains(&evaluation.leader));
+        // Each card is scored the same as in the optimizer's team
+        for (card, card_id) in evaluation.cards.iter().zip(card_ids.iter()) {
+            let best_card = best
+                .best_team
+                .values()
+                .find(|it| it.card_id == *card_id)
+                .unwrap();
+            assert_eq!(card.card_id, *card_id);
+            assert_eq!(card.score, best_card.score);
+            assert_eq!(card.power, best_card.power);
+            assert_eq!(card.bp_mul, best_card.bp_mul);
+            assert_eq!(card.skill_mul, 1.0);
+        }
+        // Items the optimizer didn't pick lose score
+        let selection = ItemSelection {
+            magazine: *Parameter::ALL
+                .iter()
+                .find(|it| **it != selection.magazine)
+                .unwrap(),
+            prop: *Attribute::ALL
+                .iter()
+                .find(|it| **it != selection.prop)
+                .unwrap(),
+            ..selection
+        };
+        let other_items = evaluate(&card_ids, &selection).unwrap();
+        assert!(other_items.score < evaluation.score);
+        assert_eq!(
+            other_items.gap,
+            best.score as i64 - other_items.score as i64
+        );
+        assert!(evaluate(&card_ids[..4], &selection).is_err());
+        let mut card_ids = card_ids;
+        card_ids[4] = card_ids[0];
+        assert!(evaluate(&card_ids, &selection).is_err());
+    }
+}
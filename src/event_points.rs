@@ -0,0 +1,288 @@
+//! The game doesn't publish its event point formulas. The divisors, base points and
+//! placement tables below are the values players report from their own lives, they are
+//! not checked against in-game results here; `points_test` pins worked examples only.
+//! So the formulas stay inside the crate, JS only gets them through `gene_points_score`.
+
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::song_calculate::team_song_score;
+use crate::user_data::*;
+use crate::{item_choices, pick_team, profile_skill_rates, CalcCard, CalcResult, EventType};
+
+/// Extra points by placement in a normal event multi live, 1st to 5th
+const MULTI_PLACEMENT_POINTS: [u32; 5] = [20, 15, 10, 5, 0];
+/// Base points by placement in a VS (Team Live Festival) live, 1st to 5th
+const VS_PLACEMENT_POINTS: [u32; 5] = [60, 52, 44, 36, 28];
+/// Base points by placement in a tour live, 1st to 5th
+const TOUR_PLACEMENT_POINTS: [u32; 5] = [40, 35, 30, 25, 20];
+
+/// Points for a 1-based placement, 0 for missing or out of range placement
+fn placement_points(table: &[u32; 5], placement: u8) -> u32 {
+    match placement {
+        1..=5 => table[placement as usize - 1],
+        _ => 0,
+    }
+}
+
+/// Apply team event bonus, then the flame (or challenge point) multiplier.
+/// Points too large for u32 are capped at `u32::MAX`.
+fn with_bonus(base: u32, event_bonus: f64, multiplier: u32) -> u32 {
+    let points = (base as f64 * (1.0 + event_bonus)).floor() as u64 * multiplier as u64;
+    points.min(u32::MAX as u64) as u32
+}
+
+/// Normal event: 50 points and one point per 5000 score.
+/// `placement` is the multi live placement, `None` for solo live.
+pub fn normal_points(score: u32, event_bonus: f64, flame: u32, placement: Option<u8>) -> u32 {
+    let base = 50
+        + score / 5000
+        + placement
+            .map(|p| placement_points(&MULTI_PLACEMENT_POINTS, p))
+            .unwrap_or(0);
+    with_bonus(base, event_bonus, flame)
+}
+
+/// Challenge event: 20 points and one point per 10000 score,
+/// multiplied by the challenge points spent instead of flames
+pub fn challenge_points(score: u32, event_bonus: f64, challenge_multiplier: u32) -> u32 {
+    with_bonus(20 + score / 10000, event_bonus, challenge_multiplier)
+}
+
+/// VS (Team Live Festival) event: placement points and one point per 5500 score.
+/// VS events have no event bonus.
+pub fn vs_points(score: u32, flame: u32, placement: u8) -> u32 {
+    let base = placement_points(&VS_PLACEMENT_POINTS, placement) + score / 5500;
+    with_bonus(base, 0.0, flame)
+}
+
+/// Live Goals event: lives give normal event points, goals only give rewards
+pub fn live_goals_points(score: u32, event_bonus: f64, flame: u32, placement: Option<u8>) -> u32 {
+    normal_points(score, event_bonus, flame, placement)
+}
+
+/// Mission Live event: lives give normal event points, missions only give rewards
+pub fn mission_live_points(score: u32, event_bonus: f64, flame: u32, placement: Option<u8>) -> u32 {
+    normal_points(score, event_bonus, flame, placement)
+}
+
+/// Medley event: 50 points for each of the three songs
+/// and one point per 5000 of the total score
+pub fn medley_points(total_score: u32, event_bonus: f64, flame: u32) -> u32 {
+    with_bonus(150 + total_score / 5000, event_bonus, flame)
+}
+
+/// Tour event: placement points and one point per 10000 score
+pub fn tour_points(score: u32, event_bonus: f64, flame: u32, placement: u8) -> u32 {
+    let base = placement_points(&TOUR_PLACEMENT_POINTS, placement) + score / 10000;
+    with_bonus(base, event_bonus, flame)
+}
+
+/// Best team by the event points of one live
+#[derive(Serialize)]
+pub struct PointsResult {
+    result: CalcResult,
+    /// Expected score of the team in the song
+    song_score: f64,
+    /// Event point bonus of the team, summed over its cards
+    team_bonus: f64,
+    /// Expected event points of one live
+    points: u32,
+}
+
+/// Expected score of the team in the song, its event point bonus summed over its cards
+/// and the event points of one live.
+/// `multiplier` and `placement` are the same as in `EventType::points`.
+#[allow(clippy::too_many_arguments)]
+pub fn team_points(
+    cards: &HashMap<String, Card>,
+    team: &HashMap<u8, CalcCard>,
+    event_bonus: &EventBonus,
+    song_data: &[SongNote],
+    song_level: u32,
+    skills: &HashMap<String, Skill>,
+    event_type: EventType,
+    multiplier: u32,
+    placement: Option<u8>,
+) -> (f64, f64, u32) {
+    let mut members: Vec<_> = team.values().collect();
+    members.sort_by_key(|it| it.character_id);
+    let power: u32 = members.iter().map(|it| it.power).sum();
+    let skill_ids: Vec<u8> = members.iter().map(|it| it.skill_id).collect();
+    let skill_levels: Vec<u8> = members.iter().map(|it| it.skill_level).collect();
+    let (song_score, _) = team_song_score(
+        power,
+        &skill_ids,
+        &skill_levels,
+        song_level,
+        false,
+        0.97,
+        song_data,
+        skills,
+    );
+    let team_bonus: f64 = members
+        .iter()
+        .map(|it| event_bonus.team_bonus_of(&cards[&it.card_id.to_string()], it.card_id))
+        .sum();
+    let points = event_type.points(song_score as u32, team_bonus, multiplier, placement);
+    (song_score, team_bonus, points)
+}
+
+/// Rank the best team of every area item choice by the event points of one live.
+/// Each team is still picked greedily by the optimizer's objective, this only plays
+/// those teams in the song and keeps the one whose points are highest, so a team outside
+/// them that earns more points isn't found.
+/// `multiplier` and `placement` are the same as in `EventType::points`.
+#[allow(clippy::too_many_arguments)]
+pub fn calc_max_points(
+    cards: &HashMap<String, Card>,
+    user_profile: &UserProfile,
+    event_bonus: &EventBonus,
+    character_band: &HashMap<u8, u8>,
+    song_data: &[SongNote],
+    song_level: u32,
+    skills: &HashMap<String, Skill>,
+    event_type: EventType,
+    multiplier: u32,
+    placement: Option<u8>,
+) -> Option<PointsResult> {
+    let skill_rates = profile_skill_rates(
+        cards,
+        user_profile,
+        song_data,
+        song_level,
+        skills,
+        event_type,
+    );
+    let choices = item_choices(
+        cards,
+        user_profile,
+        event_bonus,
+        character_band,
+        event_type,
+        &skill_rates,
+    );
+    let mut best: Option<PointsResult> = None;
+    for choice in choices.iter() {
+        let (team, score) = pick_team(&choice.calc_cards);
+        if team.is_empty() {
+            continue;
+        }
+        let (song_score, team_bonus, points) = team_points(
+            cards,
+            &team,
+            event_bonus,
+            song_data,
+            song_level,
+            skills,
+            event_type,
+            multiplier,
+            placement,
+        );
+        if best.as_ref().map_or(true, |best| points > best.points) {
+            best = Some(PointsResult {
+                result: CalcResult::new(
+                    team,
+                    choice.items.band_id,
+                    !choice.items.everyone_bonus.is_empty(),
+                    Some(choice.items.magazine_name),
+                    Some(choice.items.prop_name),
+                    score,
+                ),
+                song_score,
+                team_bonus,
+                points,
+            });
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calc_max_score;
+    use crate::read_json::*;
+
+    #[test]
+    fn points_test() {
+        // 50 + 100 = 150, * 1.5 = 225, * 3 flames
+        assert_eq!(normal_points(500000, 0.5, 3, None), 675);
+        // 50 + 100 + 20 for the 1st place
+        assert_eq!(normal_points(500000, 0.0, 1, Some(1)), 170);
+        assert_eq!(challenge_points(500000, 0.0, 5), 350);
+        // 28 for the 5th place + 100
+        assert_eq!(vs_points(550000, 1, 5), 128);
+        assert_eq!(medley_points(1500000, 0.0, 1), 450);
+        assert_eq!(tour_points(500000, 0.0, 1, 0), 50);
+        // Large multipliers are capped instead of overflowing
+        assert_eq!(challenge_points(u32::MAX, 0.0, u32::MAX), u32::MAX);
+    }
+
+    #[test]
+    fn max_points_test() {
+        let Fixture {
+            cards: all_cards,
+            user_profile,
+            character_band,
+            song_notes,
+            skills,
+            ..
+        } = read_fixture();
+        let event_bonus = summer_vacation_bonus();
+        let best = calc_max_points(
+            &all_cards,
+            &user_profile,
+            &event_bonus,
+            &character_band,
+            &song_notes,
+            26,
+            &skills,
+            EventType::Free,
+            3,
+            None,
+        )
+        .unwrap();
+        assert_eq!(best.result.best_team.len(), 5);
+        assert_eq!(
+            best.points,
+            EventType::Free.points(best.song_score as u32, best.team_bonus, 3, None)
+        );
+        // The team bonus is the event bonus of the members, summed in any order
+        let team_bonus: f64 = best
+            .result
+            .best_team
+            .values()
+            .map(|it| event_bonus.team_bonus_of(&all_cards[&it.card_id.to_string()], it.card_id))
+            .sum();
+        assert!((best.team_bonus - team_bonus).abs() < 1e-9);
+        // The score optimizer's team is among the teams tried, it earns no more points
+        let score_team = calc_max_score(
+            &all_cards,
+            &user_profile,
+            &event_bonus,
+            &character_band,
+            &song_notes,
+            26,
+            &skills,
+            EventType::Free,
+        );
+        let mut members: Vec<_> = score_team.best_team.values().collect();
+        members.sort_by_key(|it| it.character_id);
+        let (song_score, _) = team_song_score(
+            members.iter().map(|it| it.power).sum(),
+            &members.iter().map(|it| it.skill_id).collect::<Vec<u8>>(),
+            &members.iter().map(|it| it.skill_level).collect::<Vec<u8>>(),
+            26,
+            false,
+            0.97,
+            &song_notes,
+            &skills,
+        );
+        let team_bonus: f64 = members
+            .iter()
+            .map(|it| event_bonus.team_bonus_of(&all_cards[&it.card_id.to_string()], it.card_id))
+            .sum();
+        assert!(best.points >= EventType::Free.points(song_score as u32, team_bonus, 3, None));
+    }
+}
@@ -0,0 +1,250 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::constraints::pick_constrained_team;
+use crate::user_data::*;
+use crate::{
+    best_of_choices, best_of_choices_with, item_choices, profile_skill_rates, CalcResult, EventType,
+};
+
+/// What keeps a card out of the best team
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case", tag = "limit")]
+pub enum Limit {
+    /// The card's character is taken by a better card
+    SameCharacter { card_id: u32 },
+    /// The card doesn't have the event attribute
+    NoAttributeBonus,
+    /// The card's character isn't an event character
+    NoCharacterBonus,
+    /// The card's skill is weaker than every skill in the best team
+    WeakSkill,
+    /// The card is below its level limit
+    LowLevel { level: u8, max_level: u8 },
+    /// The card can be trained but isn't
+    NotTrained,
+}
+
+/// Why a card is or isn't in the best team
+#[derive(Serialize)]
+pub struct CardExplanation {
+    card_id: u32,
+    /// The card is in the best team
+    selected: bool,
+    /// Best team that includes the card
+    team: CalcResult,
+    /// Score of the best team
+    best_score: u32,
+    /// Score lost by putting the card on the team
+    loss: i64,
+    limits: Vec<Limit>,
+}
+
+/// Find the best team that includes the card and what keeps the card out of the best team
+#[allow(clippy::too_many_arguments)]
+pub fn explain_card(
+    cards: &HashMap<String, Card>,
+    user_profile: &UserProfile,
+    event_bonus: &EventBonus,
+    character_band: &HashMap<u8, u8>,
+    song_data: &[SongNote],
+    song_level: u32,
+    skills: &HashMap<String, Skill>,
+    event_type: EventType,
+    card_id: u32,
+) -> Result<CardExplanation, String> {
+    let card = cards
+        .get(&card_id.to_string())
+        .ok_or(format!("Card {} not found", card_id))?;
+    let card_stat = user_profile
+        .card_status
+        .iter()
+        .find(|it| it.id == card_id)
+        .ok_or(format!("Card {} is not in the profile", card_id))?;
+    if card_stat.exclude || !card.released_by(user_profile.server, user_profile.as_of) {
+        return Err(format!("Card {} is not usable in the profile", card_id));
+    }
+    let skill_rates = profile_skill_rates(
+        cards,
+        user_profile,
+        song_data,
+        song_level,
+        skills,
+        event_type,
+    );
+    let choices = item_choices(
+        cards,
+        user_profile,
+        event_bonus,
+        character_band,
+        event_type,
+        &skill_rates,
+    );
+    let best = best_of_choices(&choices, event_bonus, character_band);
+    let allowed: HashSet<u32> = user_profile.card_status.iter().map(|it| it.id).collect();
+    let team = best_of_choices_with(&choices, event_bonus, character_band, |calc_cards| {
+        pick_constrained_team(calc_cards, &[card_id], &allowed)
+    });
+
+    let mut limits: Vec<Limit> = Vec::new();
+    if let Some(other) = best.best_team.get(&card.character_id) {
+        if other.card_id != card_id {
+            limits.push(Limit::SameCharacter {
+                card_id: other.card_id,
+            });
+        }
+    }
+    if event_bonus.prop.is_some_and(|prop| prop != card.attribute) {
+        limits.push(Limit::NoAttributeBonus);
+    }
+    if !event_bonus.event_characters().is_empty()
+        && event_bonus.character_bonus_of(card.character_id).is_none()
+    {
+        limits.push(Limit::NoCharacterBonus);
+    }
+    let weakest_skill = best
+        .best_team
+        .values()
+        .filter(|it| it.card_id != card_id)
+        .map(|it| it.skill_mul)
+        .fold(f64::INFINITY, f64::min);
+    if let Some(it) = team.best_team.get(&card.character_id) {
+        if it.skill_mul < weakest_skill {
+            limits.push(Limit::WeakSkill);
+        }
+    }
+    let max_level = card.max_level(card_stat.train);
+    if card_stat.level < max_level {
+        limits.push(Limit::LowLevel {
+            level: card_stat.level,
+            max_level,
+        });
+    }
+    // Only 3 and 4 star cards can be trained
+    if card.rarity >= 3 && card_stat.train == 0 {
+        limits.push(Limit::NotTrained);
+    }
+    Ok(CardExplanation {
+        card_id,
+        selected: best.best_team.values().any(|it| it.card_id == card_id),
+        best_score: best.score,
+        loss: best.score as i64 - team.score as i64,
+        team,
+        limits,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_json::*;
+    use crate::{calc_max_score, skill_tag};
+
+    #[test]
+    fn explain_test() {
+        let Fixture {
+            cards: all_cards,
+            user_profile,
+            character_band,
+            song_notes,
+            skills,
+            ..
+        } = read_fixture();
+        let event_bonus = summer_vacation_bonus();
+        let explain = |card_id: u32, event_type: EventType| {
+            explain_card(
+                &all_cards,
+                &user_profile,
+                &event_bonus,
+                &character_band,
+                &song_notes,
+                26,
+                &skills,
+                event_type,
+                card_id,
+            )
+        };
+        let usable: Vec<&CardStatus> = user_profile
+            .card_status
+            .iter()
+            .filter(|it| {
+                !it.exclude
+                    && all_cards.get(&it.id.to_string()).is_some_and(|card| {
+                        card.released_by(user_profile.server, user_profile.as_of)
+                    })
+            })
+            .collect();
+        // Usable cards left out of the best team have something that limits them
+        let mut explained = 0;
+        for card_stat in usable.iter() {
+            let explanation = explain(card_stat.id, EventType::Free).unwrap();
+            assert!(explanation.loss >= 0);
+            assert!(explanation
+                .team
+                .best_team
+                .values()
+                .any(|it| it.card_id == card_stat.id));
+            if !explanation.selected {
+                assert!(!explanation.limits.is_empty());
+                explained += 1;
+            }
+            if explained >= 3 {
+                break;
+            }
+        }
+        assert!(explained > 0);
+        assert!(explain(u32::MAX, EventType::Free).is_err());
+
+        // A card whose character is in the best team with another card
+        let best = calc_max_score(
+            &all_cards,
+            &user_profile,
+            &event_bonus,
+            &character_band,
+            &song_notes,
+            26,
+            &skills,
+            EventType::Free,
+        );
+        let card_stat = usable
+            .iter()
+            .find(|card_stat| {
+                let character_id = all_cards[&card_stat.id.to_string()].character_id;
+                best.best_team
+                    .get(&character_id)
+                    .is_some_and(|it| it.card_id != card_stat.id)
+            })
+            .unwrap();
+        let character_id = all_cards[&card_stat.id.to_string()].character_id;
+        let explanation = explain(card_stat.id, EventType::Free).unwrap();
+        assert!(!explanation.selected);
+        assert!(explanation.limits.iter().any(|it| matches!(
+            it,
+            Limit::SameCharacter { card_id } if *card_id == best.best_team[&character_id].card_id
+        )));
+
+        // The card with the weakest skill when skills count for the event type
+        let skill_rates = profile_skill_rates(
+            &all_cards,
+            &user_profile,
+            &song_notes,
+            26,
+            &skills,
+            EventType::VS,
+        );
+        let skill_rate = |card_stat: &CardStatus| {
+            let card = &all_cards[&card_stat.id.to_string()];
+            skill_rates[&skill_tag(card.skill_id, card_stat.skill)]
+        };
+        let weakest = usable
+            .iter()
+            .min_by(|a, b| skill_rate(a).partial_cmp(&skill_rate(b)).unwrap())
+            .unwrap();
+        let explanation = explain(weakest.id, EventType::VS).unwrap();
+        assert!(!explanation.selected);
+        assert!(explanation
+            .limits
+            .iter()
+            .any(|it| matches!(it, Limit::WeakSkill)));
+    }
+}
@@ -0,0 +1,209 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::event_points::team_points;
+use crate::upgrade::maxed_status;
+use crate::user_data::*;
+use crate::{calc_max_score, CalcResult, EventType};
+
+/// What one event gains from adding the candidate card
+#[derive(Serialize)]
+pub struct CandidateResult {
+    /// Position of the event in the given events
+    event: usize,
+    /// Best team score without the card
+    score: u32,
+    /// Best team score with the card
+    new_score: u32,
+    /// Score gained by adding the card
+    gain: i64,
+    /// Event points gained in one solo live without flames, from the best teams' expected
+    /// scores in the song and their event bonus
+    points_gain: i64,
+    /// The card is in the new best team
+    selected: bool,
+}
+
+/// Add a card the account doesn't have yet, or replace its status if it does,
+/// and report how much it raises the best team of every event.
+/// `card_stat` is the state the card is evaluated at, `None` means fully maxed
+/// with the lowest skill level.
+#[allow(clippy::too_many_arguments)]
+pub fn evaluate_candidate(
+    cards: &HashMap<String, Card>,
+    user_profile: &UserProfile,
+    events: &[(EventBonus, EventType)],
+    character_band: &HashMap<u8, u8>,
+    song_data: &[SongNote],
+    song_level: u32,
+    skills: &HashMap<String, Skill>,
+    card_id: u32,
+    card_stat: Option<CardStatus>,
+) -> Result<Vec<CandidateResult>, String> {
+    let card = cards
+        .get(&card_id.to_string())
+        .ok_or(format!("Card {} not found", card_id))?;
+    let mut card_stat = card_stat.unwrap_or_else(|| maxed_status(card_id, card, 0));
+    card_stat.id = card_id;
+    card_stat.exclude = false;
+    let mut candidate_profile = user_profile.clone();
+    candidate_profile.card_status.retain(|it| it.id != card_id);
+    candidate_profile.card_status.push(card_stat);
+
+    let mut results: Vec<CandidateResult> = Vec::new();
+    for (event, (event_bonus, event_type)) in events.iter().enumerate() {
+        let before = calc_max_score(
+            cards,
+            user_profile,
+            event_bonus,
+            character_band,
+            song_data,
+            song_level,
+            skills,
+            *event_type,
+        );
+        let after = calc_max_score(
+            cards,
+            &candidate_profile,
+            event_bonus,
+            character_band,
+            song_data,
+            song_level,
+            skills,
+            *event_type,
+        );
+        // The optimizer's score is weighted by the event type, so play the teams instead
+        let points_of = |result: &CalcResult| {
+            team_points(
+                cards,
+                &result.best_team,
+                event_bonus,
+                song_data,
+                song_level,
+                skills,
+                *event_type,
+                1,
+                None,
+            )
+            .2
+        };
+        let points_before = points_of(&before);
+        let points_after = points_of(&after);
+        results.push(CandidateResult {
+            event,
+            score: before.score,
+            new_score: after.score,
+            gain: after.score as i64 - before.score as i64,
+            points_gain: points_after as i64 - points_before as i64,
+            selected: after.best_team.values().any(|it| it.card_id == card_id),
+        });
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_json::*;
+    use crate::song_calculate::team_song_score;
+    use crate::CalcCard;
+
+    #[test]
+    fn candidate_test() {
+        let Fixture {
+            cards: all_cards,
+            user_profile,
+            character_band,
+            song_notes,
+            skills,
+            ..
+        } = read_fixture();
+        let events = vec![(summer_vacation_bonus(), EventType::Free)];
+        // Take a 4 star card out of the profile and pull it again
+        let card_id = user_profile
+            .card_status
+            .iter()
+            .map(|it| it.id)
+            .filter(|id| {
+                all_cards
+                    .get(&id.to_string())
+                    .is_some_and(|it| it.rarity == 4)
+            })
+            .min()
+            .unwrap();
+        let mut user_profile = user_profile;
+        user_profile.card_status.retain(|it| it.id != card_id);
+        let results = evaluate_candidate(
+            &all_cards,
+            &user_profile,
+            &events,
+            &character_band,
+            &song_notes,
+            26,
+            &skills,
+            card_id,
+            None,
+        )
+        .unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].gain >= 0);
+        // Points of a normal event live are 50 and one per 5000 of the song score,
+        // raised by the team's event bonus
+        let points_of = |user_profile: &UserProfile| {
+            let result = calc_max_score(
+                &all_cards,
+                user_profile,
+                &events[0].0,
+                &character_band,
+                &song_notes,
+                26,
+                &skills,
+                EventType::Free,
+            );
+            // Slot order by character id, as `team_points` plays them
+            let mut team: Vec<&CalcCard> = result.best_team.values().collect();
+            team.sort_by_key(|it| it.character_id);
+            let (song_score, _) = team_song_score(
+                team.iter().map(|it| it.power).sum(),
+                &team.iter().map(|it| it.skill_id).collect::<Vec<u8>>(),
+                &team.iter().map(|it| it.skill_level).collect::<Vec<u8>>(),
+                26,
+                false,
+                0.97,
+                &song_notes,
+                &skills,
+            );
+            let team_bonus: f64 = team
+                .iter()
+                .map(|it| {
+                    events[0]
+                        .0
+                        .team_bonus_of(&all_cards[&it.card_id.to_string()], it.card_id)
+                })
+                .sum();
+            ((50 + song_score as u32 / 5000) as f64 * (1.0 + team_bonus)).floor() as i64
+        };
+        let mut candidate_profile = user_profile.clone();
+        candidate_profile.card_status.push(maxed_status(
+            card_id,
+            &all_cards[&card_id.to_string()],
+            0,
+        ));
+        assert_eq!(
+            results[0].points_gain,
+            points_of(&candidate_profile) - points_of(&user_profile)
+        );
+        assert!(evaluate_candidate(
+            &all_cards,
+            &user_profile,
+            &events,
+            &character_band,
+            &song_notes,
+            26,
+            &skills,
+            u32::MAX,
+            None,
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,124 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::constraints::{allowed_cards, pick_constrained_team, TeamConstraints};
+use crate::user_data::*;
+use crate::{best_of_choices_with, item_choices, profile_skill_rates, CalcResult, EventType};
+
+/// Best team limited to one band or one attribute
+#[derive(Serialize)]
+pub struct MatrixEntry<K> {
+    /// Band id or attribute
+    key: K,
+    /// Best team, with less than five members if the profile doesn't have enough characters
+    result: CalcResult,
+}
+
+/// Best team of every band and every attribute
+#[derive(Serialize)]
+pub struct TeamMatrix {
+    bands: Vec<MatrixEntry<u8>>,
+    attributes: Vec<MatrixEntry<Attribute>>,
+}
+
+/// Find the best team of every band in `character_band` and of every attribute.
+/// Card scores are calculated once and every team is picked from them.
+#[allow(clippy::too_many_arguments)]
+pub fn calc_team_matrix(
+    cards: &HashMap<String, Card>,
+    user_profile: &UserProfile,
+    event_bonus: &EventBonus,
+    character_band: &HashMap<u8, u8>,
+    song_data: &[SongNote],
+    song_level: u32,
+    skills: &HashMap<String, Skill>,
+    event_type: EventType,
+) -> TeamMatrix {
+    let skill_rates = profile_skill_rates(
+        cards,
+        user_profile,
+        song_data,
+        song_level,
+        skills,
+        event_type,
+    );
+    let choices = item_choices(
+        cards,
+        user_profile,
+        event_bonus,
+        character_band,
+        event_type,
+        &skill_rates,
+    );
+    let best_of = |constraints: &TeamConstraints| {
+        let allowed = allowed_cards(cards, user_profile, character_band, constraints);
+        best_of_choices_with(&choices, event_bonus, character_band, |calc_cards| {
+            pick_constrained_team(calc_cards, &[], &allowed)
+        })
+    };
+
+    let mut band_ids: Vec<u8> = character_band.values().copied().collect();
+    band_ids.sort();
+    band_ids.dedup();
+    let bands = band_ids
+        .into_iter()
+        .map(|band_id| MatrixEntry {
+            key: band_id,
+            result: best_of(&TeamConstraints {
+                band: Some(band_id),
+                ..Default::default()
+            }),
+        })
+        .collect();
+    let attributes = Attribute::ALL
+        .iter()
+        .map(|attribute| MatrixEntry {
+            key: *attribute,
+            result: best_of(&TeamConstraints {
+                attribute: Some(*attribute),
+                ..Default::default()
+            }),
+        })
+        .collect();
+    TeamMatrix { bands, attributes }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_json::*;
+
+    #[test]
+    fn matrix_test() {
+        let Fixture {
+            cards: all_cards,
+            user_profile,
+            character_band,
+            song_notes,
+            skills,
+            ..
+        } = read_fixture();
+        let event_bonus = summer_vacation_bonus();
+        let matrix = calc_team_matrix(
+            &all_cards,
+            &user_profile,
+            &event_bonus,
+            &character_band,
+            &song_notes,
+            26,
+            &skills,
+            EventType::Free,
+        );
+        assert_eq!(matrix.attributes.len(), Attribute::ALL.len());
+        for entry in matrix.bands.iter() {
+            for it in entry.result.best_team.values() {
+                assert_eq!(character_band[&it.character_id], entry.key);
+            }
+        }
+        for entry in matrix.attributes.iter() {
+            for it in entry.result.best_team.values() {
+                assert_eq!(all_cards[&it.card_id.to_string()].attribute, entry.key);
+            }
+        }
+    }
+}
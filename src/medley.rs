@@ -0,0 +1,380 @@
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+use crate::song_calculate::{skill_rate_table, team_song_score};
+use crate::song_library::{Difficulty, SongLibrary};
+use crate::user_data::*;
+use crate::{
+    apply_objective, area_items_of, calc_cards_with_items, profile_skill_tags, AreaItems, CalcCard,
+    EventType,
+};
+
+/// Orders in which the three songs pick their teams
+const SONG_ORDERS: [[usize; 3]; 6] = [
+    [0, 1, 2],
+    [0, 2, 1],
+    [1, 0, 2],
+    [1, 2, 0],
+    [2, 0, 1],
+    [2, 1, 0],
+];
+
+/// Team for one song of the medley
+#[derive(Serialize)]
+pub struct MedleyTeam {
+    song_id: u32,
+    /// Cards in skill activation order
+    cards: Vec<CalcCard>,
+    /// Card id of the leader
+    leader: u32,
+    /// Expected score of the team on the song
+    score: f64,
+}
+
+/// Medley calculation result
+#[derive(Serialize)]
+pub struct MedleyResult {
+    /// One team per song, no card is used twice
+    teams: Vec<MedleyTeam>,
+    /// selected band id
+    band_id: u8,
+    /// whether the items for every band take the shared slots of the band's items
+    everyone: bool,
+    /// selected magazine
+    magazine: Parameter,
+    /// selected prop
+    prop: Attribute,
+    /// Expected total score of the three songs
+    score: f64,
+    /// Event points of the total score, without bonus and flames
+    points: u32,
+}
+
+/// Best teams found so far, with their value, the cards they're picked from and area items
+type BestTeams = (f64, Vec<CalcCard>, Vec<Vec<usize>>, AreaItems);
+
+/// Greedily give every song its best five cards, in the given song order.
+/// `values[song][card]` is the card's value on the song.
+fn assign_teams(
+    calc_cards: &[CalcCard],
+    values: &[Vec<f64>],
+    order: &[usize; 3],
+) -> Vec<Vec<usize>> {
+    let mut teams: Vec<Vec<usize>> = vec![Vec::new(); 3];
+    let mut used: HashSet<usize> = HashSet::new();
+    for song in order.iter() {
+        let mut candidates: Vec<usize> = (0..calc_cards.len())
+            .filter(|i| !used.contains(i))
+            .collect();
+        candidates.sort_by(|a, b| {
+            values[*song][*b]
+                .partial_cmp(&values[*song][*a])
+                .unwrap_or(Ordering::Equal)
+        });
+        let mut characters: HashSet<u8> = HashSet::new();
+        for i in candidates {
+            if teams[*song].len() >= 5 {
+                break;
+            }
+            if characters.insert(calc_cards[i].character_id) {
+                teams[*song].push(i);
+                used.insert(i);
+            }
+        }
+    }
+    teams
+}
+
+/// Whether `card` can take `slot` of `team` without repeating a character
+fn fits(calc_cards: &[CalcCard], team: &[usize], slot: usize, card: usize) -> bool {
+    team.iter()
+        .enumerate()
+        .all(|(k, i)| k == slot || calc_cards[*i].character_id != calc_cards[card].character_id)
+}
+
+/// Make one swap with an unused card, or between two teams, that raises the total value.
+/// Returns false if no such swap exists.
+fn improve_once(calc_cards: &[CalcCard], values: &[Vec<f64>], teams: &mut [Vec<usize>]) -> bool {
+    let used: HashSet<usize> = teams.iter().flatten().cloned().collect();
+    // Swap with unused cards
+    for t in 0..3 {
+        for slot in 0..teams[t].len() {
+            for card in 0..calc_cards.len() {
+                if used.contains(&card) || !fits(calc_cards, &teams[t], slot, card) {
+                    continue;
+                }
+                if values[t][card] > values[t][teams[t][slot]] {
+                    teams[t][slot] = card;
+                    return true;
+                }
+            }
+        }
+    }
+    // Swap between teams
+    for t1 in 0..3 {
+        for t2 in t1 + 1..3 {
+            for s1 in 0..teams[t1].len() {
+                for s2 in 0..teams[t2].len() {
+                    let (c1, c2) = (teams[t1][s1], teams[t2][s2]);
+                    let gain = values[t1][c2] + values[t2][c1] - values[t1][c1] - values[t2][c2];
+                    if gain > 1e-9
+                        && fits(calc_cards, &teams[t1], s1, c2)
+                        && fits(calc_cards, &teams[t2], s2, c1)
+                    {
+                        teams[t1][s1] = c2;
+                        teams[t2][s2] = c1;
+                        return true;
+                    }
+                }
+            }
+        }
+    }
+    false
+}
+
+/// Split the cards into three teams of different characters with the largest total value,
+/// trying every song order greedily and improving each by swaps.
+/// `values[song][card]` is the card's value on the song.
+fn assign_best(calc_cards: &[CalcCard], values: &[Vec<f64>]) -> (f64, Vec<Vec<usize>>) {
+    let mut best: (f64, Vec<Vec<usize>>) = (f64::NEG_INFINITY, vec![Vec::new(); 3]);
+    for order in SONG_ORDERS.iter() {
+        let mut teams = assign_teams(calc_cards, values, order);
+        // Every swap raises the value, limit the rounds to keep it fast
+        for _ in 0..100 {
+            if !improve_once(calc_cards, values, &mut teams) {
+                break;
+            }
+        }
+        let value: f64 = (0..3)
+            .map(|t| teams[t].iter().map(|i| values[t][*i]).sum::<f64>())
+            .sum();
+        if value > best.0 {
+            best = (value, teams);
+        }
+    }
+    best
+}
+
+/// Build three teams without reusing any card for the three medley songs,
+/// maximizing the total expected score.
+/// Fails if a song doesn't have a loaded chart of the difficulty.
+#[allow(clippy::too_many_arguments)]
+pub fn calc_medley(
+    cards: &HashMap<String, Card>,
+    user_profile: &UserProfile,
+    event_bonus: &EventBonus,
+    character_band: &HashMap<u8, u8>,
+    library: &SongLibrary,
+    song_ids: &[u32; 3],
+    difficulty: Difficulty,
+    skills: &HashMap<String, Skill>,
+) -> Result<MedleyResult, String> {
+    let mut charts: Vec<(&Vec<SongNote>, u32)> = Vec::new();
+    for song_id in song_ids.iter() {
+        match (
+            library.chart(*song_id, difficulty),
+            library.level(*song_id, difficulty),
+        ) {
+            (Some(song_data), Some(song_level)) => charts.push((song_data, song_level)),
+            _ => {
+                return Err(format!(
+                    "Song {} has no {} chart",
+                    song_id,
+                    difficulty.name()
+                ))
+            }
+        }
+    }
+    let calc_skills = profile_skill_tags(cards, user_profile);
+    let skill_rates: Vec<HashMap<u32, f64>> = charts
+        .iter()
+        .map(|(song_data, song_level)| {
+            skill_rate_table(&calc_skills, skills, song_data, *song_level, 0.97, false)
+        })
+        .collect();
+
+    let mut best: Option<BestTeams> = None;
+    for items in area_items_of(user_profile) {
+        let calc_cards =
+            calc_cards_with_items(cards, user_profile, event_bonus, character_band, &items);
+        let values: Vec<Vec<f64>> = skill_rates
+            .iter()
+            .map(|rates| {
+                calc_cards
+                    .iter()
+                    .map(|it| {
+                        let mut weighted = *it;
+                        apply_objective(&mut weighted, EventType::Medley, rates);
+                        weighted.score as f64 * weighted.skill_mul * weighted.bp_mul
+                    })
+                    .collect()
+            })
+            .collect();
+        let (value, teams) = assign_best(&calc_cards, &values);
+        if best
+            .as_ref()
+            .map_or(true, |(best_value, ..)| value > *best_value)
+        {
+            best = Some((value, calc_cards, teams, items));
+        }
+    }
+
+    let (_, calc_cards, teams, items) = best.ok_or("The profile has no area items")?;
+    let mut medley_teams: Vec<MedleyTeam> = Vec::new();
+    for (t, team) in teams.iter().enumerate() {
+        let team: Vec<CalcCard> = team.iter().map(|i| calc_cards[*i]).collect();
+        let power: u32 = team.iter().map(|it| it.power).sum();
+        let skill_ids: Vec<u8> = team.iter().map(|it| it.skill_id).collect();
+        let skill_levels: Vec<u8> = team.iter().map(|it| it.skill_level).collect();
+        let (song_data, song_level) = charts[t];
+        let (score, leader) = team_song_score(
+            power,
+            &skill_ids,
+            &skill_levels,
+            song_level,
+            false,
+            0.97,
+            song_data,
+            skills,
+        );
+        medley_teams.push(MedleyTeam {
+            song_id: song_ids[t],
+            leader: team.get(leader).map_or(0, |it| it.card_id),
+            cards: team,
+            score,
+        });
+    }
+    let score: f64 = medley_teams.iter().map(|team| team.score).sum();
+    Ok(MedleyResult {
+        teams: medley_teams,
+        band_id: items.band_id,
+        everyone: !items.everyone_bonus.is_empty(),
+        magazine: items.magazine_name,
+        prop: items.prop_name,
+        score,
+        points: EventType::Medley.points(score as u32, 0.0, 1, None),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_json::*;
+
+    /// Best total value by trying every card in each of the three teams or in none
+    fn brute_force(calc_cards: &[CalcCard], values: &[Vec<f64>]) -> f64 {
+        let mut best = 0.0;
+        for code in 0..4usize.pow(calc_cards.len() as u32) {
+            let mut teams: Vec<Vec<usize>> = vec![Vec::new(); 3];
+            let mut rest = code;
+            for card in 0..calc_cards.len() {
+                if rest % 4 < 3 {
+                    teams[rest % 4].push(card);
+                }
+                rest /= 4;
+            }
+            let valid = teams.iter().all(|team| {
+                let characters: HashSet<u8> =
+                    team.iter().map(|i| calc_cards[*i].character_id).collect();
+                team.len() <= 5 && characters.len() == team.len()
+            });
+            if !valid {
+                continue;
+            }
+            let value: f64 = (0..3)
+                .map(|t| teams[t].iter().map(|i| values[t][*i]).sum::<f64>())
+                .sum();
+            if value > best {
+                best = value;
+            }
+        }
+        best
+    }
+
+    #[test]
+    fn medley_test() {
+        let Fixture {
+            cards: all_cards,
+            user_profile,
+            character_band,
+            skills,
+            ..
+        } = read_fixture();
+        let library = read_song_library(
+            String::from("docs/songs.json"),
+            String::from("docs"),
+            Difficulty::Expert,
+        )
+        .unwrap();
+        let event_bonus = summer_vacation_bonus();
+        let song_ids = library.charts_of(Difficulty::Expert);
+        let result = calc_medley(
+            &all_cards,
+            &user_profile,
+            &event_bonus,
+            &character_band,
+            &library,
+            &[song_ids[0], song_ids[1], song_ids[2]],
+            Difficulty::Expert,
+            &skills,
+        )
+        .unwrap();
+        let mut used: HashSet<u32> = HashSet::new();
+        for team in result.teams.iter() {
+            let characters: HashSet<u8> = team.cards.iter().map(|it| it.character_id).collect();
+            assert_eq!(characters.len(), team.cards.len());
+            for it in team.cards.iter() {
+                assert!(used.insert(it.card_id), "Card {} is reused", it.card_id);
+            }
+        }
+        // Songs without a chart are reported
+        let missing = calc_medley(
+            &all_cards,
+            &user_profile,
+            &event_bonus,
+            &character_band,
+            &library,
+            &[song_ids[0], song_ids[1], u32::MAX],
+            Difficulty::Expert,
+            &skills,
+        );
+        assert!(missing.is_err());
+
+        // Small pools of the profile split as well as trying every split
+        let items = &area_items_of(&user_profile)[0];
+        let calc_cards = calc_cards_with_items(
+            &all_cards,
+            &user_profile,
+            &event_bonus,
+            &character_band,
+            items,
+        );
+        let calc_skills = profile_skill_tags(&all_cards, &user_profile);
+        let skill_rates: Vec<HashMap<u32, f64>> = song_ids[..3]
+            .iter()
+            .map(|song_id| {
+                let song_data = library.chart(*song_id, Difficulty::Expert).unwrap();
+                let song_level = library.level(*song_id, Difficulty::Expert).unwrap();
+                skill_rate_table(&calc_skills, &skills, song_data, song_level, 0.97, false)
+            })
+            .collect();
+        for pool in calc_cards.chunks(8).take(3) {
+            let pool: Vec<CalcCard> = pool.to_vec();
+            let values: Vec<Vec<f64>> = skill_rates
+                .iter()
+                .map(|rates| {
+                    pool.iter()
+                        .map(|it| {
+                            let mut weighted = *it;
+                            apply_objective(&mut weighted, EventType::Medley, rates);
+                            weighted.score as f64 * weighted.skill_mul * weighted.bp_mul
+                        })
+                        .collect()
+                })
+                .collect();
+            let (value, _) = assign_best(&pool, &values);
+            assert!((value - brute_force(&pool, &values)).abs() < 1e-6);
+        }
+    }
+}
@@ -1,7 +1,12 @@
 #![allow(dead_code)]
 /// This crate has been used in tests, but rustc doesn't recognize it...
-use crate::{Card, Character, Band, RawUserProfile, SongNote, Skill};
+use crate::{
+    band_items_new, character_band_new, event_bonus_of, Attribute, Band, Card, Character, Event,
+    EventBonus, EventType, Parameter, RawUserProfile, Server, Skill, Song, SongNote, UserProfile,
+};
+use crate::song_library::{Difficulty, SongLibrary};
 use std::fs::File;
+use std::path::Path;
 use std::io::prelude::*;
 use std::collections::HashMap;
 
@@ -35,19 +40,14 @@ pub fn read_raw_user_profile(path: String) -> Result<RawUserProfile, Box<dyn std
 pub fn character_band_new_from_string(
     characters_path: String,
     bands_path: String,
-) -> Result<HashMap<u8, String>, Box<dyn std::error::Error>> {
-    let mut character_band: HashMap<u8, String> = HashMap::new();
+) -> Result<HashMap<u8, u8>, Box<dyn std::error::Error>> {
     let characters = read_characters(characters_path)?;
     let bands = read_bands(bands_path)?;
-    for (character_id, character) in characters.iter() {
-        let character_id = character_id.parse::<u8>().unwrap_or(1);
-        let band = bands.get(&character.band_id.to_string()).unwrap();
-        character_band.insert(
-            character_id,
-            band.band_name[1].as_str().unwrap().to_string(),
-        );
-    }
-    Ok(character_band)
+    Ok(character_band_new(&characters, &bands))
+}
+
+pub fn read_band_items(path: String) -> Result<HashMap<String, u8>, Box<dyn std::error::Error>> {
+    Ok(band_items_new(&read_bands(path)?))
 }
 
 pub fn read_song_notes(path: String) -> Result<Vec<SongNote>, Box<dyn std::error::Error>> {
@@ -59,3 +59,92 @@ pub fn read_skill(path: String) -> Result<HashMap<String, Skill>, Box<dyn std::e
     let buffer = read_to_str(path)?;
     Ok(serde_json::from_str(buffer.as_str())?)
 }
+
+pub fn read_songs(path: String) -> Result<HashMap<String, Song>, Box<dyn std::error::Error>> {
+    let buffer = read_to_str(path)?;
+    Ok(serde_json::from_str(buffer.as_str())?)
+}
+
+/// Read songs.json and every chart of the given difficulty found in `charts_dir`.
+/// Charts are named like Bestdori's, e.g. `125.expert.json`.
+pub fn read_song_library(
+    songs_path: String,
+    charts_dir: String,
+    difficulty: Difficulty,
+) -> Result<SongLibrary, Box<dyn std::error::Error>> {
+    let mut library = SongLibrary::new(read_songs(songs_path)?);
+    let mut song_ids: Vec<u32> = library
+        .songs
+        .keys()
+        .filter_map(|id| id.parse::<u32>().ok())
+        .collect();
+    song_ids.sort();
+    for song_id in song_ids {
+        let chart_path =
+            Path::new(&charts_dir).join(format!("{}.{}.json", song_id, difficulty.name()));
+        if !chart_path.exists() {
+            continue;
+        }
+        let notes = read_song_notes(chart_path.to_string_lossy().to_string())?;
+        library.add_chart(song_id, difficulty, notes);
+    }
+    Ok(library)
+}
+
+pub fn read_events(path: String) -> Result<HashMap<String, Event>, Box<dyn std::error::Error>> {
+    let buffer = read_to_str(path)?;
+    Ok(serde_json::from_str(buffer.as_str())?)
+}
+
+/// Read the event bonus and event type of an event held on the server
+pub fn read_event_bonus(
+    path: String,
+    event_id: u32,
+    server: Server,
+) -> Result<(EventBonus, EventType), Box<dyn std::error::Error>> {
+    let events = read_events(path)?;
+    Ok(event_bonus_of(&events, event_id, server)?)
+}
+
+/// Cards, profile and chart in docs that most tests build teams from
+pub struct Fixture {
+    pub cards: HashMap<String, Card>,
+    pub raw_user_profile: RawUserProfile,
+    pub user_profile: UserProfile,
+    pub character_band: HashMap<u8, u8>,
+    pub band_items: HashMap<String, u8>,
+    /// Chart of song 125 on expert, level 26
+    pub song_notes: Vec<SongNote>,
+    pub skills: HashMap<String, Skill>,
+}
+
+pub fn read_fixture() -> Fixture {
+    let raw_user_profile = read_raw_user_profile(String::from("docs/user_profile.json")).unwrap();
+    let band_items = read_band_items(String::from("docs/bands.json")).unwrap();
+    Fixture {
+        cards: read_cards(String::from("docs/cards.json")).unwrap(),
+        user_profile: UserProfile::new(&raw_user_profile, &band_items),
+        raw_user_profile,
+        character_band: character_band_new_from_string(
+            String::from("docs/characters.json"),
+            String::from("docs/bands.json"),
+        )
+        .unwrap(),
+        band_items,
+        song_notes: read_song_notes(String::from("docs/125.expert.json")).unwrap(),
+        skills: read_skill(String::from("docs/skills.json")).unwrap(),
+    }
+}
+
+/// 只属于我们的SUMMER VACATION, happy cards of characters 16 to 20 with technique
+pub fn summer_vacation_bonus() -> EventBonus {
+    EventBonus {
+        prop: Some(Attribute::Happy),
+        characters: vec![16, 17, 18, 19, 20],
+        prop_bonus: 0.1,
+        character_bonus: 0.2,
+        parameter: Some(Parameter::Technique),
+        all_fit_bonus: 0.0,
+        ..Default::default()
+    }
+}
@@ -30,18 +30,9 @@ pub fn skill_bonus(skill: &Skill, accurate: f64, index: u32) -> f64 {
     for (k, v) in skill.activation_effect.activate_effect_types.iter() {
         match k.as_str() {
             "score" | "score_over_life" | "score_under_life" | "score_continued_note_judge" => {
-                let effect_value = if m
-                    && skill
-                        .activation_effect
-                        .unification_activate_effect_value
-                        .is_some()
-                {
-                    skill
-                        .activation_effect
-                        .unification_activate_effect_value
-                        .unwrap()
-                } else {
-                    v.activate_effect_value[0].as_u64().unwrap_or(0) as u32
+                let effect_value = match skill.activation_effect.unification_activate_effect_value {
+                    Some(value) if m => value,
+                    _ => v.activate_effect_value[0].as_u64().unwrap_or(0) as u32,
                 };
                 if k == "score_continued_note_judge" {
                     a = 1.0 + effect_value as f64 / 100.0;
@@ -78,24 +69,23 @@ pub fn skill_bonus(skill: &Skill, accurate: f64, index: u32) -> f64 {
 
 /// Calculate the skill bonus in real song
 pub fn song_score(
-    skill_ids: &Vec<u8>,
-    skill_levels: &Vec<u8>,
+    skill_ids: &[u8],
+    skill_levels: &[u8],
     song_level: u32,
     has_fever: bool,
     accuracy: f64,
-    song_data: &Vec<SongNote>,
+    song_data: &[SongNote],
     skills: &HashMap<String, Skill>,
 ) -> f64 {
     let accuracy_rate = 1.1 * accuracy + 0.8 * (1.0 - accuracy);
     let song_level_rate = (3.0 + 0.03 * (song_level as f64 - 5.0)) / song_data.len() as f64;
     let mut final_score = 0f64;
     let mut skill_end = 0.0;
-    let mut combo_count = 0;
     let mut skill_order = 0;
     let mut y = 0;
-    for note in song_data.iter() {
+    for (combo_count, note) in song_data.iter().enumerate() {
         // Basic bonus
-        let mut bonus = accuracy_rate * song_level_rate * combo_bonus(combo_count);
+        let mut bonus = accuracy_rate * song_level_rate * combo_bonus(combo_count as u32);
         // Fever
         bonus *= match note.fever {
             Some(_) => match has_fever {
@@ -108,56 +98,91 @@ pub fn song_score(
         if note.time < skill_end {
             y += 1;
             let skill = &skills[&skill_ids[skill_order - 1].to_string()];
-            bonus *= skill_bonus(&skill, accuracy, y);
+            bonus *= skill_bonus(skill, accuracy, y);
         }
-        match note.skill {
-            Some(_) => {
-                skill_order += 1;
-                y = 0;
-                let skill = &skills[&skill_ids[skill_order - 1].to_string()];
-                skill_end = note.time + skill.duration[skill_levels[skill_order - 1] as usize];
-                bonus *= skill_bonus(&skill, accuracy, y);
-                // NOTE: Missing Center position bonus...
-            }
-            None => {}
+        if note.skill.is_some() {
+            skill_order += 1;
+            y = 0;
+            let skill = &skills[&skill_ids[skill_order - 1].to_string()];
+            // Skill levels the skill doesn't have are taken as level 0, see `validate_profile`
+            let duration = skill
+                .duration
+                .get(skill_levels[skill_order - 1] as usize)
+                .or(skill.duration.first());
+            skill_end = note.time + duration.copied().unwrap_or_default();
+            bonus *= skill_bonus(skill, accuracy, y);
+            // NOTE: Missing Center position bonus...
         };
         final_score += bonus;
-        combo_count += 1;
     }
     final_score
 }
 
-/// Generate song-skill cache
-pub fn cache_table(
-    calc_skills: &Vec<u32>,
-    skills: &HashMap<String, Skill>,
-    song_data: &Vec<SongNote>,
+/// Calculate a team's expected score in real song.
+/// Members activate their skills in slot order (wrapping around for teams smaller than five),
+/// then the leader activates again, so every member is tried as the leader
+/// and the best one is returned with its index.
+#[allow(clippy::too_many_arguments)]
+pub fn team_song_score(
+    power: u32,
+    skill_ids: &[u8],
+    skill_levels: &[u8],
     song_level: u32,
-    accurate: f64,
     has_fever: bool,
-) -> HashMap<u32, HashMap<u32, f64>> {
-    let mut table: HashMap<u32, HashMap<u32, f64>> = HashMap::new();
-    for it1 in calc_skills.iter() {
-        let s1 = it1 % 10;
-        let l1 = (it1 - s1) / 10;
-        let mut temp: HashMap<u32, f64> = HashMap::new();
-        for it2 in calc_skills.iter() {
-            let s2 = it2 % 10;
-            let l2 = (it2 - s2) / 10;
-            temp.insert(
-                *it2,
-                song_score(
-                    &vec![l2 as u8, l2 as u8, l2 as u8, l2 as u8, l2 as u8, l1 as u8],
-                    &vec![s2 as u8, s2 as u8, s2 as u8, s2 as u8, s2 as u8, s1 as u8],
-                    song_level,
-                    has_fever,
-                    accurate,
-                    song_data,
-                    skills,
-                ),
+    accuracy: f64,
+    song_data: &[SongNote],
+    skills: &HashMap<String, Skill>,
+) -> (f64, usize) {
+    let mut best = (0.0, 0);
+    for leader in 0..skill_ids.len() {
+        let mut order_ids: Vec<u8> = (0..5).map(|i| skill_ids[i % skill_ids.len()]).collect();
+        order_ids.push(skill_ids[leader]);
+        let mut order_levels: Vec<u8> = (0..5)
+            .map(|i| skill_levels[i % skill_levels.len()])
+            .collect();
+        order_levels.push(skill_levels[leader]);
+        let score = power as f64
+            * song_score(
+                &order_ids,
+                &order_levels,
+                song_level,
+                has_fever,
+                accuracy,
+                song_data,
+                skills,
             );
+        if score > best.0 {
+            best = (score, leader);
         }
-        table.insert(*it1, temp);
+    }
+    best
+}
+
+/// Generate song-skill cache of teams whose members all have the same skill
+pub fn skill_rate_table(
+    calc_skills: &[u32],
+    skills: &HashMap<String, Skill>,
+    song_data: &[SongNote],
+    song_level: u32,
+    accurate: f64,
+    has_fever: bool,
+) -> HashMap<u32, f64> {
+    let mut table: HashMap<u32, f64> = HashMap::new();
+    for it in calc_skills.iter() {
+        let s = it % 10;
+        let l = (it - s) / 10;
+        table.insert(
+            *it,
+            song_score(
+                &[l as u8; 6],
+                &[s as u8; 6],
+                song_level,
+                has_fever,
+                accurate,
+                song_data,
+                skills,
+            ),
+        );
     }
     table
 }
@@ -181,8 +206,10 @@ mod tests {
             character_id: 12,
             score: 53505,
             skill_id: 4,
+            skill_level: 0,
             skill_mul: 0.5,
             bp_mul: 1.0,
+            power: 53505,
         };
         // 极其梦幻的生物
         let calc_card2 = CalcCard {
@@ -190,12 +217,14 @@ mod tests {
             character_id: 12,
             score: 63880,
             skill_id: 13,
+            skill_level: 0,
             skill_mul: 0.5,
             bp_mul: 1.0,
+            power: 63880,
         };
         let score1 = song_score(
-            &vec![calc_card.skill_id; 6],
-            &vec![0; 6],
+            &[calc_card.skill_id; 6],
+            &[0; 6],
             26,
             false,
             0.97,
@@ -203,8 +232,8 @@ mod tests {
             &skills,
         );
         let score2 = song_score(
-            &vec![calc_card2.skill_id; 6],
-            &vec![0; 6],
+            &[calc_card2.skill_id; 6],
+            &[0; 6],
             26,
             false,
             0.97,
@@ -222,7 +251,7 @@ mod tests {
             120, 180, 40, 140, 70, 200, 100, 260, 90, 60, 30, 110, 130, 170, 250, 240,
         ];
         let song_notes = read_song_notes(String::from("docs/125.expert.json")).unwrap();
-        let table = cache_table(&tags, &skills, &song_notes, 26, 0.97, false);
+        let table = skill_rate_table(&tags, &skills, &song_notes, 26, 0.97, false);
         println!("{}", to_string(&table).unwrap());
     }
 }
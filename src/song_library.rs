@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::user_data::{Song, SongNote};
+
+/// Song difficulty, in the same order as Bestdori's `difficulty` keys
+#[wasm_bindgen]
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+    Expert,
+    Special,
+}
+
+impl Difficulty {
+    /// Key used in songs.json
+    pub fn key(&self) -> String {
+        (*self as u8).to_string()
+    }
+
+    /// Name used in chart file names, such as `125.expert.json`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+            Difficulty::Expert => "expert",
+            Difficulty::Special => "special",
+        }
+    }
+}
+
+/// Song catalog with the charts that have been loaded
+pub struct SongLibrary {
+    /// Songs from songs.json, keyed by song id
+    pub songs: HashMap<String, Song>,
+    /// Charts keyed by song id and difficulty
+    charts: HashMap<(u32, Difficulty), Vec<SongNote>>,
+}
+
+impl SongLibrary {
+    pub fn new(songs: HashMap<String, Song>) -> SongLibrary {
+        SongLibrary {
+            songs,
+            charts: HashMap::new(),
+        }
+    }
+
+    pub fn add_chart(&mut self, song_id: u32, difficulty: Difficulty, notes: Vec<SongNote>) {
+        self.charts.insert((song_id, difficulty), notes);
+    }
+
+    pub fn chart(&self, song_id: u32, difficulty: Difficulty) -> Option<&Vec<SongNote>> {
+        self.charts.get(&(song_id, difficulty))
+    }
+
+    /// Play level of the chart, such as 26 for A to Z expert
+    pub fn level(&self, song_id: u32, difficulty: Difficulty) -> Option<u32> {
+        self.songs
+            .get(&song_id.to_string())?
+            .difficulty
+            .get(&difficulty.key())
+            .map(|d| d.play_level)
+    }
+
+    /// Song ids of the loaded charts of the given difficulty, sorted
+    pub fn charts_of(&self, difficulty: Difficulty) -> Vec<u32> {
+        let mut song_ids: Vec<u32> = self
+            .charts
+            .keys()
+            .filter(|(_, d)| *d == difficulty)
+            .map(|(song_id, _)| *song_id)
+            .collect();
+        song_ids.sort();
+        song_ids
+    }
+}
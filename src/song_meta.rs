@@ -0,0 +1,148 @@
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::song_calculate::team_song_score;
+use crate::song_library::{Difficulty, SongLibrary};
+use crate::user_data::Skill;
+use crate::{CalcCard, EventType};
+
+/// Seconds spent outside the song in every live, such as loading and the result screen
+const LIVE_OVERHEAD: f64 = 30.0;
+
+/// What the song meta is ranked by
+#[wasm_bindgen]
+#[derive(Copy, Clone)]
+pub enum MetaOrder {
+    Score,
+    ScorePerMinute,
+    PointsPerHour,
+}
+
+/// Team performance on one chart
+#[derive(Serialize)]
+pub struct SongMeta {
+    song_id: u32,
+    song_level: u32,
+    /// Expected score of the team
+    score: f64,
+    /// Expected score per minute of song length
+    score_per_minute: f64,
+    /// Event points per hour of continuous play, without bonus and flames
+    points_per_hour: f64,
+}
+
+impl SongMeta {
+    fn key(&self, order: MetaOrder) -> f64 {
+        match order {
+            MetaOrder::Score => self.score,
+            MetaOrder::ScorePerMinute => self.score_per_minute,
+            MetaOrder::PointsPerHour => self.points_per_hour,
+        }
+    }
+}
+
+/// Run the team on every loaded chart of the difficulty and rank the songs
+pub fn calc_song_meta(
+    team: &[CalcCard],
+    library: &SongLibrary,
+    difficulty: Difficulty,
+    skills: &HashMap<String, Skill>,
+    has_fever: bool,
+    event_type: EventType,
+    order: MetaOrder,
+) -> Vec<SongMeta> {
+    let power: u32 = team.iter().map(|card| card.power).sum();
+    let skill_ids: Vec<u8> = team.iter().map(|card| card.skill_id).collect();
+    let skill_levels: Vec<u8> = team.iter().map(|card| card.skill_level).collect();
+    let mut metas: Vec<SongMeta> = Vec::new();
+    for song_id in library.charts_of(difficulty) {
+        let song_level = match library.level(song_id, difficulty) {
+            Some(song_level) => song_level,
+            None => continue,
+        };
+        let song_data = library.chart(song_id, difficulty).unwrap();
+        let length = library.songs[&song_id.to_string()].length;
+        let (score, _) = team_song_score(
+            power,
+            &skill_ids,
+            &skill_levels,
+            song_level,
+            has_fever,
+            0.97,
+            song_data,
+            skills,
+        );
+        metas.push(SongMeta {
+            song_id,
+            song_level,
+            score,
+            score_per_minute: score / length * 60.0,
+            points_per_hour: event_type.points(score as u32, 0.0, 1, None) as f64 * 3600.0
+                / (length + LIVE_OVERHEAD),
+        });
+    }
+    metas.sort_by(|a, b| {
+        b.key(order)
+            .partial_cmp(&a.key(order))
+            .unwrap_or(Ordering::Equal)
+    });
+    metas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_json::*;
+
+    #[test]
+    fn song_meta_test() {
+        let skills = read_skill(String::from("docs/skills.json")).unwrap();
+        let library = read_song_library(
+            String::from("docs/songs.json"),
+            String::from("docs"),
+            Difficulty::Expert,
+        )
+        .unwrap();
+        let team: Vec<CalcCard> = [588, 589, 683, 466, 382]
+            .iter()
+            .enumerate()
+            .map(|(i, card_id)| CalcCard {
+                card_id: *card_id,
+                character_id: i as u8 + 11,
+                score: 60000,
+                skill_id: 4,
+                skill_level: 0,
+                skill_mul: 1.0,
+                bp_mul: 1.0,
+                power: 60000,
+            })
+            .collect();
+        let meta_of = |team: &Vec<CalcCard>| {
+            calc_song_meta(
+                team,
+                &library,
+                Difficulty::Expert,
+                &skills,
+                false,
+                EventType::Free,
+                MetaOrder::PointsPerHour,
+            )
+        };
+        let metas = meta_of(&team);
+        assert!(!metas.is_empty());
+        for pair in metas.windows(2) {
+            assert!(pair[0].points_per_hour >= pair[1].points_per_hour);
+        }
+        // The event bonus in the cards' score doesn't play in the song
+        let boosted: Vec<CalcCard> = team
+            .iter()
+            .map(|it| CalcCard {
+                score: it.score * 2,
+                ..*it
+            })
+            .collect();
+        assert_eq!(meta_of(&boosted)[0].score, metas[0].score);
+    }
+}
@@ -0,0 +1,632 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use crate::song_calculate::skill_rate_table;
+use crate::user_data::*;
+use crate::{
+    apply_objective, area_items_of, best_of_choices, calc_card_with_items, calc_max_score,
+    item_choice, item_choices, pick_team, profile_skill_rates, profile_skill_tags, skill_tag,
+    AreaItems, CalcCard, CalcResult, EventType,
+};
+
+/// Investment that can be made on a card
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Upgrade {
+    /// Train the card, only 3 and 4 star cards can be trained
+    Train,
+    /// Unlock the next episode
+    Episode,
+    /// Raise the level to the level limit
+    Level,
+    /// Raise the skill level by one
+    Skill,
+}
+
+impl Upgrade {
+    const ALL: [Upgrade; 4] = [
+        Upgrade::Train,
+        Upgrade::Episode,
+        Upgrade::Level,
+        Upgrade::Skill,
+    ];
+
+    /// Card status after the upgrade and how many steps it takes,
+    /// `None` if the card can't take the upgrade
+    fn apply(
+        &self,
+        card: &Card,
+        card_stat: &CardStatus,
+        skills: &HashMap<String, Skill>,
+    ) -> Option<(CardStatus, u32)> {
+        let mut upgraded = card_stat.clone();
+        let steps = match self {
+            Upgrade::Train if card.rarity >= 3 && card_stat.train == 0 => {
+                upgraded.train = 1;
+                1
+            }
+            Upgrade::Episode if card_stat.ep < card.episode_count() => {
+                upgraded.ep += 1;
+                1
+            }
+            Upgrade::Level => {
+                let level_limit = card.max_level(card_stat.train);
+                if card_stat.level >= level_limit {
+                    return None;
+                }
+                upgraded.level = level_limit;
+                (level_limit - card_stat.level) as u32
+            }
+            Upgrade::Skill => {
+                // Skill levels start from 0
+                let max_skill = skills.get(&card.skill_id.to_string())?.duration.len();
+                let skill = card_stat.skill.checked_add(1)?;
+                if skill as usize >= max_skill {
+                    return None;
+                }
+                upgraded.skill = skill;
+                1
+            }
+            _ => return None,
+        };
+        Some((upgraded, steps))
+    }
+}
+
+/// Status of the card at max level, trained if it can be, with every episode unlocked
+pub fn maxed_status(card_id: u32, card: &Card, skill_level: u8) -> CardStatus {
+    let train = if card.rarity >= 3 { 1 } else { 0 };
+    CardStatus {
+        id: card_id,
+        level: card.max_level(train),
+        exclude: false,
+        art: train,
+        train,
+        ep: card.episode_count(),
+        skill: skill_level,
+    }
+}
+
+/// Profile with the selected cards, or every card when `card_ids` is `None`, fully maxed.
+/// Skill levels are raised to `skill_level`, capped by the skill's highest level,
+/// but never lowered.
+pub fn project_profile(
+    cards: &HashMap<String, Card>,
+    user_profile: &UserProfile,
+    skills: &HashMap<String, Skill>,
+    card_ids: Option<&Vec<u32>>,
+    skill_level: u8,
+) -> UserProfile {
+    let mut projected = user_profile.clone();
+    for card_stat in projected.card_status.iter_mut() {
+        if card_ids.is_some_and(|ids| !ids.contains(&card_stat.id)) {
+            continue;
+        }
+        let card = match cards.get(&card_stat.id.to_string()) {
+            Some(card) => card,
+            None => continue,
+        };
+        let max_skill = skills
+            .get(&card.skill_id.to_string())
+            .map_or(card_stat.skill, |it| {
+                it.duration.len().saturating_sub(1) as u8
+            });
+        let skill = skill_level.min(max_skill).max(card_stat.skill);
+        let maxed = maxed_status(card_stat.id, card, skill);
+        *card_stat = CardStatus {
+            exclude: card_stat.exclude,
+            ..maxed
+        };
+    }
+    projected
+}
+
+/// Current best team next to the best team of the projected profile
+#[derive(Serialize)]
+pub struct ProjectionResult {
+    current: CalcResult,
+    /// Best team with the selected cards fully maxed
+    projected: CalcResult,
+}
+
+/// Find the best team of the profile and of its fully maxed projection
+#[allow(clippy::too_many_arguments)]
+pub fn calc_projection(
+    cards: &HashMap<String, Card>,
+    user_profile: &UserProfile,
+    event_bonus: &EventBonus,
+    character_band: &HashMap<u8, u8>,
+    song_data: &[SongNote],
+    song_level: u32,
+    skills: &HashMap<String, Skill>,
+    event_type: EventType,
+    card_ids: Option<&Vec<u32>>,
+    skill_level: u8,
+) -> ProjectionResult {
+    let projected = project_profile(cards, user_profile, skills, card_ids, skill_level);
+    ProjectionResult {
+        current: calc_max_score(
+            cards,
+            user_profile,
+            event_bonus,
+            character_band,
+            song_data,
+            song_level,
+            skills,
+            event_type,
+        ),
+        projected: calc_max_score(
+            cards,
+            &projected,
+            event_bonus,
+            character_band,
+            song_data,
+            song_level,
+            skills,
+            event_type,
+        ),
+    }
+}
+
+/// Best team score after one upgrade
+#[derive(Serialize)]
+pub struct UpgradeResult {
+    card_id: u32,
+    upgrade: Upgrade,
+    /// Best team score after the upgrade
+    score: u32,
+    /// Score gained over the current best team
+    gain: i64,
+    /// Gain per unit of material, when the upgrade's cost is supplied
+    gain_per_cost: Option<f64>,
+}
+
+/// Try every possible upgrade of every card and rank them by the best team score they gain.
+/// `costs` is the material cost of one step of each upgrade, such as one level.
+#[allow(clippy::too_many_arguments)]
+pub fn plan_upgrades(
+    cards: &HashMap<String, Card>,
+    user_profile: &UserProfile,
+    event_bonus: &EventBonus,
+    character_band: &HashMap<u8, u8>,
+    song_data: &[SongNote],
+    song_level: u32,
+    skills: &HashMap<String, Skill>,
+    event_type: EventType,
+    costs: &HashMap<Upgrade, f64>,
+) -> Vec<UpgradeResult> {
+    // Cache skill mul table, with the skill levels cards can be raised to
+    let (_, skill_weight) = event_type.objective();
+    let skill_rates = if skill_weight > 0.0 {
+        let mut calc_skills = profile_skill_tags(cards, user_profile);
+        for card_stat in user_profile.card_status.iter() {
+            let card = match cards.get(&card_stat.id.to_string()) {
+                Some(card) => card,
+                None => continue,
+            };
+            if let Some((upgraded, _)) = Upgrade::Skill.apply(card, card_stat, skills) {
+                calc_skills.push(skill_tag(card.skill_id, upgraded.skill));
+            }
+        }
+        calc_skills.sort();
+        calc_skills.dedup();
+        skill_rate_table(&calc_skills, skills, song_data, song_level, 0.97, false)
+    } else {
+        HashMap::new()
+    };
+    let choices = item_choices(
+        cards,
+        user_profile,
+        event_bonus,
+        character_band,
+        event_type,
+        &skill_rates,
+    );
+    let base_score = best_of_choices(&choices, event_bonus, character_band).score;
+
+    let mut results: Vec<UpgradeResult> = Vec::new();
+    for card_stat in user_profile.card_status.iter() {
+        if card_stat.exclude {
+            continue;
+        }
+        let card = match cards.get(&card_stat.id.to_string()) {
+            Some(card) => card,
+            None => continue,
+        };
+        if !card.released_by(user_profile.server, user_profile.as_of) {
+            continue;
+        }
+        for upgrade in Upgrade::ALL.iter() {
+            let (upgraded, steps) = match upgrade.apply(card, card_stat, skills) {
+                Some(upgraded) => upgraded,
+                None => continue,
+            };
+            // Only the upgraded card changes, so replace it in every item choice and pick again
+            let mut score = 0;
+            for choice in choices.iter() {
+                let mut it = calc_card_with_items(
+                    card,
+                    &upgraded,
+                    event_bonus,
+                    character_band,
+                    &choice.items,
+                );
+                apply_objective(&mut it, event_type, &skill_rates);
+                let mut calc_cards: Vec<CalcCard> = choice
+                    .calc_cards
+                    .iter()
+                    .filter(|c| c.card_id != card_stat.id)
+                    .cloned()
+                    .collect();
+                let position = calc_cards
+                    .iter()
+                    .position(|c| *c < it)
+                    .unwrap_or(calc_cards.len());
+                calc_cards.insert(position, it);
+                score = score.max(pick_team(&calc_cards).1);
+            }
+            let gain = score as i64 - base_score as i64;
+            results.push(UpgradeResult {
+                card_id: card_stat.id,
+                upgrade: *upgrade,
+                score,
+                gain,
+                gain_per_cost: costs
+                    .get(upgrade)
+                    .map(|cost| cost * steps as f64)
+                    .filter(|cost| *cost > 0.0)
+                    .map(|cost| gain as f64 / cost),
+            });
+        }
+    }
+    results.sort_by_key(|it| Reverse(it.gain));
+    results
+}
+
+/// Best team scores after raising one area item by one level
+#[derive(Serialize)]
+pub struct ItemUpgradeResult {
+    /// Item name, such as Roselia, Menu
+    item: String,
+    /// Position in the item's level array
+    index: usize,
+    /// Level after the upgrade
+    level: u8,
+    /// Best team score of each event after the upgrade, in the order of the events
+    scores: Vec<u32>,
+    /// Share of each event's current best team score gained, summed over the events.
+    /// Event types weight the score differently, so only shares are comparable.
+    gain: f64,
+}
+
+/// Best team score of every area item choice of an event
+struct EventChoices<'a> {
+    event_bonus: &'a EventBonus,
+    event_type: EventType,
+    skill_rates: HashMap<u32, f64>,
+    scores: Vec<(AreaItems, u32)>,
+}
+
+impl EventChoices<'_> {
+    /// Best team score of the event over every choice of the profile's area items.
+    /// Choices the event already has a score for aren't calculated again.
+    fn best_score(
+        &self,
+        cards: &HashMap<String, Card>,
+        user_profile: &UserProfile,
+        character_band: &HashMap<u8, u8>,
+    ) -> u32 {
+        area_items_of(user_profile)
+            .into_iter()
+            .map(
+                |items| match self.scores.iter().find(|(it, _)| *it == items) {
+                    Some((_, score)) => *score,
+                    None => {
+                        let choice = item_choice(
+                            cards,
+                            user_profile,
+                            self.event_bonus,
+                            character_band,
+                            self.event_type,
+                            &self.skill_rates,
+                            items,
+                        );
+                        pick_team(&choice.calc_cards).1
+                    }
+                },
+            )
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Raise every area item by one level and rank them by the share of the best team score
+/// they gain, summed over the events.
+/// `max_levels` is the highest level of each item, keyed by its name in the profile.
+/// Items without a highest level, and items that are neither a band nor an area item,
+/// are left out.
+#[allow(clippy::too_many_arguments)]
+pub fn plan_item_upgrades(
+    cards: &HashMap<String, Card>,
+    raw_user_profile: &RawUserProfile,
+    band_items: &HashMap<String, u8>,
+    max_levels: &HashMap<String, u8>,
+    as_of: Option<u64>,
+    events: &[(EventBonus, EventType)],
+    character_band: &HashMap<u8, u8>,
+    song_data: &[SongNote],
+    song_level: u32,
+    skills: &HashMap<String, Skill>,
+) -> Vec<ItemUpgradeResult> {
+    let mut user_profile = UserProfile::new(raw_user_profile, band_items);
+    user_profile.as_of = as_of;
+    // Card status doesn't change, so the skill tables are shared by every upgrade,
+    // and an upgrade only changes the choices that hold the upgraded item
+    let events: Vec<EventChoices> = events
+        .iter()
+        .map(|(event_bonus, event_type)| {
+            let skill_rates = profile_skill_rates(
+                cards,
+                &user_profile,
+                song_data,
+                song_level,
+                skills,
+                *event_type,
+            );
+            let scores = item_choices(
+                cards,
+                &user_profile,
+                event_bonus,
+                character_band,
+                *event_type,
+                &skill_rates,
+            )
+            .into_iter()
+            .map(|choice| {
+                let score = pick_team(&choice.calc_cards).1;
+                (choice.items, score)
+            })
+            .collect();
+            EventChoices {
+                event_bonus,
+                event_type: *event_type,
+                skill_rates,
+                scores,
+            }
+        })
+        .collect();
+    let base_scores: Vec<u32> = events
+        .iter()
+        .map(|event| {
+            event
+                .scores
+                .iter()
+                .map(|(_, score)| *score)
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let mut item_names: Vec<&String> = raw_user_profile
+        .items
+        .keys()
+        .filter(|it| !user_profile.unknown_items.contains(it))
+        .collect();
+    item_names.sort();
+    let mut results: Vec<ItemUpgradeResult> = Vec::new();
+    for item in item_names {
+        let max_level = match max_levels.get(item) {
+            Some(max_level) => *max_level,
+            None => continue,
+        };
+        for (index, level) in raw_user_profile.items[item].iter().enumerate() {
+            if *level >= max_level {
+                continue;
+            }
+            let mut raw = raw_user_profile.clone();
+            raw.items.get_mut(item).unwrap()[index] = level + 1;
+            let mut upgraded = UserProfile::new(&raw, band_items);
+            upgraded.as_of = as_of;
+            let scores: Vec<u32> = events
+                .iter()
+                .map(|event| event.best_score(cards, &upgraded, character_band))
+                .collect();
+            let gain = scores
+                .iter()
+                .zip(base_scores.iter())
+                .filter(|(_, base)| **base > 0)
+                .map(|(score, base)| (*score as f64 - *base as f64) / *base as f64)
+                .sum();
+            results.push(ItemUpgradeResult {
+                item: item.clone(),
+                index,
+                level: level + 1,
+                scores,
+                gain,
+            });
+        }
+    }
+    results.sort_by(|a, b| b.gain.total_cmp(&a.gain));
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_json::*;
+
+    #[test]
+    fn upgrade_test() {
+        let Fixture {
+            cards: all_cards,
+            user_profile,
+            character_band,
+            song_notes,
+            skills,
+            ..
+        } = read_fixture();
+        let event_bonus = summer_vacation_bonus();
+        let costs: HashMap<Upgrade, f64> =
+            serde_json::from_str(r#"{"train": 1.0, "episode": 1.0, "level": 0.1, "skill": 2.0}"#)
+                .unwrap();
+        let results = plan_upgrades(
+            &all_cards,
+            &user_profile,
+            &event_bonus,
+            &character_band,
+            &song_notes,
+            26,
+            &skills,
+            EventType::VS,
+            &costs,
+        );
+        assert!(!results.is_empty());
+        for pair in results.windows(2) {
+            assert!(pair[0].gain >= pair[1].gain);
+        }
+        // Upgrades never make the best team worse
+        assert!(results.iter().all(|it| it.gain >= 0));
+        assert!(results.iter().all(|it| it.gain_per_cost.is_some()));
+        // Skill levels at the top of the range can't be raised
+        let card_stat = &user_profile.card_status[0];
+        let card = &all_cards[&card_stat.id.to_string()];
+        let mut maxed = card_stat.clone();
+        maxed.skill = u8::MAX;
+        assert!(Upgrade::Skill.apply(card, &maxed, &skills).is_none());
+    }
+
+    #[test]
+    fn projection_test() {
+        let Fixture {
+            cards: all_cards,
+            user_profile,
+            character_band,
+            song_notes,
+            skills,
+            ..
+        } = read_fixture();
+        let event_bonus = summer_vacation_bonus();
+        let result = calc_projection(
+            &all_cards,
+            &user_profile,
+            &event_bonus,
+            &character_band,
+            &song_notes,
+            26,
+            &skills,
+            EventType::Challenge,
+            None,
+            4,
+        );
+        assert!(result.projected.score >= result.current.score);
+        let card_id = user_profile.card_status[0].id;
+        let projected =
+            project_profile(&all_cards, &user_profile, &skills, Some(&vec![card_id]), 0);
+        for (before, after) in user_profile
+            .card_status
+            .iter()
+            .zip(projected.card_status.iter())
+        {
+            assert!(after.level >= before.level);
+            if before.id != card_id {
+                assert_eq!(after.level, before.level);
+            }
+        }
+    }
+
+    #[test]
+    fn item_upgrade_test() {
+        let Fixture {
+            cards: all_cards,
+            raw_user_profile,
+            character_band,
+            band_items,
+            song_notes,
+            skills,
+            ..
+        } = read_fixture();
+        let events = vec![
+            (summer_vacation_bonus(), EventType::Free),
+            (
+                EventBonus {
+                    prop: Some(Attribute::Pure),
+                    characters: vec![11, 12, 13, 14, 15],
+                    prop_bonus: 0.1,
+                    character_bonus: 0.2,
+                    parameter: Some(Parameter::Technique),
+                    all_fit_bonus: 0.2,
+                    ..Default::default()
+                },
+                EventType::VS,
+            ),
+        ];
+        // Unknown items aren't upgraded even with a highest level
+        let mut raw_user_profile = raw_user_profile;
+        raw_user_profile
+            .items
+            .insert(String::from("NewBand"), vec![1; 7]);
+        let mut max_levels: HashMap<String, u8> = raw_user_profile
+            .items
+            .keys()
+            .map(|it| (it.clone(), 6))
+            .collect();
+        max_levels.insert(String::from("Magazine"), 5);
+        max_levels.remove("Plaza");
+        let results = plan_item_upgrades(
+            &all_cards,
+            &raw_user_profile,
+            &band_items,
+            &max_levels,
+            None,
+            &events,
+            &character_band,
+            &song_notes,
+            26,
+            &skills,
+        );
+        assert!(!results.is_empty());
+        for pair in results.windows(2) {
+            assert!(pair[0].gain >= pair[1].gain);
+        }
+        assert!(results.iter().all(|it| it.gain >= 0.0));
+        assert!(results.iter().all(|it| it.level <= max_levels[&it.item]));
+        // Scores match the optimizer on the upgraded profile, and the gain is the share of
+        // each event's current score gained
+        let best_scores = |raw_user_profile: &RawUserProfile| {
+            let user_profile = UserProfile::new(raw_user_profile, &band_items);
+            events
+                .iter()
+                .map(|(event_bonus, event_type)| {
+                    calc_max_score(
+                        &all_cards,
+                        &user_profile,
+                        event_bonus,
+                        &character_band,
+                        &song_notes,
+                        26,
+                        &skills,
+                        *event_type,
+                    )
+                    .score
+                })
+                .collect::<Vec<u32>>()
+        };
+        let base_scores = best_scores(&raw_user_profile);
+        let best = &results[0];
+        let mut upgraded = raw_user_profile.clone();
+        upgraded.items.get_mut(&best.item).unwrap()[best.index] = best.level;
+        assert_eq!(best.scores, best_scores(&upgraded));
+        let gain: f64 = best
+            .scores
+            .iter()
+            .zip(base_scores.iter())
+            .map(|(score, base)| (*score as f64 - *base as f64) / *base as f64)
+            .sum();
+        assert!((best.gain - gain).abs() < 1e-9);
+        assert!(results
+            .iter()
+            .all(|it| it.item != "NewBand" && it.item != "Plaza"));
+        assert!(results.iter().any(|it| it.item == "Magazine"));
+    }
+}
@@ -1,33 +1,164 @@
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::OnceLock;
 
-/// Raw user profile from bestdori
-#[derive(Deserialize)]
+use crate::compression::decompress;
+use crate::EventType;
+
+/// Card attribute
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Attribute {
+    Powerful,
+    Cool,
+    Happy,
+    Pure,
+}
+
+impl Attribute {
+    pub const ALL: [Attribute; 4] = [
+        Attribute::Powerful,
+        Attribute::Cool,
+        Attribute::Happy,
+        Attribute::Pure,
+    ];
+
+    /// Name used in bestdori's data
+    pub fn name(&self) -> &'static str {
+        match self {
+            Attribute::Powerful => "powerful",
+            Attribute::Cool => "cool",
+            Attribute::Happy => "happy",
+            Attribute::Pure => "pure",
+        }
+    }
+}
+
+/// Card parameter
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Parameter {
+    Performance,
+    Technique,
+    Visual,
+}
+
+impl Parameter {
+    pub const ALL: [Parameter; 3] = [
+        Parameter::Performance,
+        Parameter::Technique,
+        Parameter::Visual,
+    ];
+}
+
+/// Game server, stored as its index in bestdori's per-server arrays
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum Server {
+    Jp,
+    En,
+    Tw,
+    Cn,
+    Kr,
+}
+
+impl Server {
+    const ALL: [Server; 5] = [Server::Jp, Server::En, Server::Tw, Server::Cn, Server::Kr];
+
+    /// Index in bestdori's per-server arrays
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+impl TryFrom<u8> for Server {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Server::Jp),
+            1 => Ok(Server::En),
+            2 => Ok(Server::Tw),
+            3 => Ok(Server::Cn),
+            4 => Ok(Server::Kr),
+            _ => Err(format!("Unknown server {}", value)),
+        }
+    }
+}
+
+impl From<Server> for u8 {
+    fn from(server: Server) -> u8 {
+        server as u8
+    }
+}
+
+/// Raw user profile from bestdori, its card data is decompressed when it's loaded
+#[derive(Deserialize, Clone)]
+#[serde(try_from = "ExportedUserProfile")]
 pub struct RawUserProfile {
     name: String,
-    server: u8,
+    server: Server,
+    /// Encoded card data, see `decode_data`
+    pub data: String,
+    /// Area item levels, keyed by item name such as Roselia, Menu
+    pub items: HashMap<String, Vec<u8>>,
+}
+
+/// User profile as bestdori exports it, see `decompress` for the compression schemes
+#[derive(Deserialize)]
+struct ExportedUserProfile {
+    name: String,
+    server: Server,
+    #[serde(default)]
     compression: String,
     data: String,
     items: HashMap<String, Vec<u8>>,
 }
 
+impl TryFrom<ExportedUserProfile> for RawUserProfile {
+    type Error = String;
+
+    fn try_from(exported: ExportedUserProfile) -> Result<Self, Self::Error> {
+        Ok(RawUserProfile {
+            data: decompress(&exported.compression, &exported.data)?,
+            name: exported.name,
+            server: exported.server,
+            items: exported.items,
+        })
+    }
+}
+
+/// Levels that training adds to the card's level limit
+pub const TRAIN_LEVEL_BONUS: u8 = 10;
+
 /// Number of the card data, include performance, technique, visual
-#[derive(Deserialize)]
+#[derive(Deserialize, Copy, Clone)]
 pub struct CardData {
     pub performance: u32,
     pub technique: u32,
     pub visual: u32,
 }
 
+impl CardData {
+    pub fn get(&self, parameter: Parameter) -> u32 {
+        match parameter {
+            Parameter::Performance => self.performance,
+            Parameter::Technique => self.technique,
+            Parameter::Visual => self.visual,
+        }
+    }
+}
+
 /// Card base data
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Card {
     pub character_id: u8,
     pub rarity: u8,
-    pub attribute: String,
+    pub attribute: Attribute,
     pub level_limit: u8,
     pub resource_set_name: String,
     pub prefix: Vec<Value>,
@@ -36,6 +167,20 @@ pub struct Card {
     #[serde(rename = "type")]
     pub type_: String,
     pub stat: HashMap<String, Value>, // TODO Find a way to type it
+    /// `stat` parsed on first use, a card is scored once per area item choice
+    #[serde(skip)]
+    parsed_stat: OnceLock<ParsedStat>,
+}
+
+/// Stats read from a card's `stat`
+#[derive(Default)]
+struct ParsedStat {
+    /// Stats of every level in the card's own stat, sorted by level
+    levels: Vec<(u8, CardData)>,
+    /// Stats each episode adds, in order
+    episodes: Vec<CardData>,
+    /// Stats training adds
+    training: Option<CardData>,
 }
 
 /// Character data
@@ -55,20 +200,67 @@ pub struct Band {
     pub band_name: Value,
 }
 
+/// Name in the server's language from bestdori's per-server names.
+/// Names missing in the language fall back to the other servers' names, Japanese first.
+pub fn localized_name(names: &[Value], server: Server) -> Option<String> {
+    std::iter::once(server)
+        .chain(Server::ALL.iter().copied())
+        .find_map(|server| names.get(server.index())?.as_str().map(String::from))
+}
+
+impl Character {
+    /// Character name in the server's language
+    pub fn name(&self, server: Server) -> Option<String> {
+        localized_name(self.character_name.as_array()?, server)
+    }
+}
+
+impl Band {
+    /// Band name in the server's language
+    pub fn name(&self, server: Server) -> Option<String> {
+        localized_name(self.band_name.as_array()?, server)
+    }
+}
+
+#[derive(Clone)]
 pub struct Magazine {
     pub performance: f64,
     pub technique: f64,
     pub visual: f64,
 }
 
+impl Magazine {
+    pub fn get(&self, parameter: Parameter) -> f64 {
+        match parameter {
+            Parameter::Performance => self.performance,
+            Parameter::Technique => self.technique,
+            Parameter::Visual => self.visual,
+        }
+    }
+}
+
 /// This library's own user profile
+#[derive(Clone)]
 pub struct UserProfile {
+    #[allow(dead_code)]
     pub name: String,
-    pub server: u8,
-    /// Band items, such as gtitar
-    pub bands: HashMap<String, Vec<f64>>,
+    pub server: Server,
+    /// Evaluation date in milliseconds, cards released after it can't be used.
+    /// `None` means every card with a release date on the server can be used.
+    pub as_of: Option<u64>,
+    /// Band items keyed by band id, such as gtitar
+    pub bands: HashMap<u8, Vec<f64>>,
+    /// Items for every band, they take the last slots of a band's items and raise
+    /// every card. Empty until their bonus per level is verified
+    pub everyone: Vec<f64>,
+    /// Item names in the raw profile that are neither a band nor a known area item
+    pub unknown_items: Vec<String>,
+    /// Area items missing from the raw profile, they are taken as level 0
+    pub missing_items: Vec<String>,
+    /// Card entries of the raw data that can't be decoded, they are left out
+    pub invalid_entries: Vec<String>,
     /// Property items, such as food
-    pub props: HashMap<String, Vec<f64>>,
+    pub props: HashMap<Attribute, Vec<f64>>,
     /// Magazine
     pub magazine: Magazine,
     /// Card status
@@ -76,23 +268,275 @@ pub struct UserProfile {
 }
 
 /// Event bonus
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, Default)]
 pub struct EventBonus {
-    /// Property, such as happy, cool
-    pub prop: String,
+    /// Property, such as happy, cool, `null` for events without one
+    #[serde(deserialize_with = "required_option")]
+    pub prop: Option<Attribute>,
     /// Character ids
+    #[serde(default)]
     pub characters: Vec<u8>,
     /// Property bonus
     pub prop_bonus: f64,
     /// Character bonus
+    #[serde(default)]
     pub character_bonus: f64,
-    /// Parameter, such as performance, technique
-    pub parameter: String,
+    /// Parameter, such as performance, technique, `null` for events without one
+    #[serde(deserialize_with = "required_option")]
+    pub parameter: Option<Parameter>,
     /// All fit parameter bonus
     pub all_fit_bonus: f64,
+    /// Share of the event parameter's stat added for cards with both the event attribute
+    /// and an event character, `DEFAULT_PARAMETER_BONUS` when not given
+    #[serde(default)]
+    pub parameter_bonus: Option<f64>,
+    /// Per-character bonus, overrides `character_bonus` for these characters
+    #[serde(default)]
+    pub character_bonuses: HashMap<u8, f64>,
+    /// Extra bonus of specific cards, keyed by card id
+    #[serde(default)]
+    pub card_bonuses: HashMap<u32, f64>,
+}
+
+/// Deserialize an `Option` whose key must be given, `null` for none
+fn required_option<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::deserialize(deserializer)
+}
+
+/// Parameter bonus of event bonuses that don't give theirs
+pub const DEFAULT_PARAMETER_BONUS: f64 = 0.5;
+
+impl EventBonus {
+    /// Share of the event parameter's stat added for fully fitting cards
+    pub fn parameter_bonus(&self) -> f64 {
+        self.parameter_bonus.unwrap_or(DEFAULT_PARAMETER_BONUS)
+    }
+
+    /// Bonus of an event character, `None` if the character isn't one
+    pub fn character_bonus_of(&self, character_id: u8) -> Option<f64> {
+        match self.character_bonuses.get(&character_id) {
+            Some(bonus) => Some(*bonus),
+            None if self.characters.contains(&character_id) => Some(self.character_bonus),
+            None => None,
+        }
+    }
+
+    /// Event point bonus the card gives its team, from its character, attribute and card id
+    pub fn team_bonus_of(&self, card: &Card, card_id: u32) -> f64 {
+        let character_bonus = self.character_bonus_of(card.character_id);
+        let prop_bonus = match self.prop == Some(card.attribute) {
+            true => Some(self.prop_bonus),
+            false => None,
+        };
+        let all_fit_bonus = match character_bonus.is_some() && prop_bonus.is_some() {
+            true => self.all_fit_bonus,
+            false => 0.0,
+        };
+        character_bonus.unwrap_or(0.0)
+            + prop_bonus.unwrap_or(0.0)
+            + self.card_bonuses.get(&card_id).copied().unwrap_or(0.0)
+            + all_fit_bonus
+    }
+
+    /// Event characters of both the flat and the per-character form, sorted
+    pub fn event_characters(&self) -> Vec<u8> {
+        let mut characters: Vec<u8> = self.characters.clone();
+        characters.extend(self.character_bonuses.keys());
+        characters.sort();
+        characters.dedup();
+        characters
+    }
+}
+
+/// Event data from bestdori's events.json
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Event {
+    /// Such as story, challenge, versus
+    pub event_type: String,
+    #[allow(dead_code)]
+    pub event_name: Vec<Value>,
+    /// Start time of every server, in milliseconds
+    pub start_at: Vec<Value>,
+    /// End time of every server, in milliseconds
+    pub end_at: Vec<Value>,
+    #[serde(default)]
+    pub attributes: Vec<EventAttribute>,
+    #[serde(default)]
+    pub characters: Vec<EventCharacter>,
+    /// Bonus cards
+    #[serde(default)]
+    pub members: Vec<EventMember>,
+    pub event_attribute_and_character_bonus: Option<EventAttributeAndCharacterBonus>,
+    pub event_character_parameter_bonus: Option<CardData>,
+}
+
+#[derive(Deserialize)]
+pub struct EventAttribute {
+    pub attribute: Attribute,
+    pub percent: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventCharacter {
+    pub character_id: u8,
+    pub percent: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventMember {
+    /// Card id
+    pub situation_id: u32,
+    pub percent: u32,
+}
+
+/// Bonus when both attribute and character fit
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventAttributeAndCharacterBonus {
+    pub point_percent: u32,
+    pub parameter_percent: u32,
+}
+
+/// Read a bestdori timestamp, which is a string of milliseconds
+pub fn timestamp_of(value: &Value) -> Option<u64> {
+    match value {
+        Value::String(s) => s.parse::<u64>().ok(),
+        _ => value.as_u64(),
+    }
+}
+
+impl Event {
+    /// Start and end time on the server in milliseconds, `None` if not held there
+    pub fn period(&self, server: Server) -> Option<(u64, u64)> {
+        let start_at = timestamp_of(self.start_at.get(server.index())?)?;
+        let end_at = timestamp_of(self.end_at.get(server.index())?)?;
+        Some((start_at, end_at))
+    }
+
+    /// Event type used by the optimizer, `None` for unknown event types
+    pub fn calc_event_type(&self) -> Option<EventType> {
+        match self.event_type.as_str() {
+            "story" => Some(EventType::Free),
+            "challenge" => Some(EventType::Challenge),
+            "versus" | "festival" => Some(EventType::VS),
+            "live_try" => Some(EventType::LiveGoals),
+            "mission_live" => Some(EventType::MissionLive),
+            "medley" => Some(EventType::Medley),
+            _ => None,
+        }
+    }
+
+    /// Build the event bonus from the event's attribute, characters and bonus cards
+    pub fn event_bonus(&self) -> EventBonus {
+        let percentage = |v: u32| v as f64 / 100.0;
+        let (prop, prop_bonus) = match self.attributes.first() {
+            Some(attribute) => (Some(attribute.attribute), percentage(attribute.percent)),
+            None => (None, 0.0),
+        };
+        // The parameter with the largest bonus is the event parameter
+        let parameter = self
+            .event_character_parameter_bonus
+            .as_ref()
+            .and_then(|bonus| {
+                Parameter::ALL
+                    .iter()
+                    .filter(|parameter| bonus.get(**parameter) > 0)
+                    .max_by_key(|parameter| bonus.get(**parameter))
+                    .copied()
+            });
+        EventBonus {
+            prop,
+            characters: self.characters.iter().map(|c| c.character_id).collect(),
+            prop_bonus,
+            character_bonus: 0.0,
+            parameter,
+            all_fit_bonus: self
+                .event_attribute_and_character_bonus
+                .as_ref()
+                .map_or(0.0, |bonus| percentage(bonus.point_percent)),
+            parameter_bonus: self
+                .event_attribute_and_character_bonus
+                .as_ref()
+                .map(|bonus| percentage(bonus.parameter_percent)),
+            character_bonuses: self
+                .characters
+                .iter()
+                .map(|c| (c.character_id, percentage(c.percent)))
+                .collect(),
+            card_bonuses: self
+                .members
+                .iter()
+                .map(|m| (m.situation_id, percentage(m.percent)))
+                .collect(),
+        }
+    }
+}
+
+/// Find the event held on the server, and build its event bonus and event type
+pub fn event_bonus_of(
+    events: &HashMap<String, Event>,
+    event_id: u32,
+    server: Server,
+) -> Result<(EventBonus, EventType), String> {
+    let event = events
+        .get(&event_id.to_string())
+        .ok_or(format!("Event {} not found", event_id))?;
+    if event.period(server).is_none() {
+        return Err(format!(
+            "Event {} is not held on server {:?}",
+            event_id, server
+        ));
+    }
+    let event_type = event
+        .calc_event_type()
+        .ok_or(format!("Unknown event type {}", event.event_type))?;
+    Ok((event.event_bonus(), event_type))
+}
+
+/// Find the event running on the server at `timestamp` (milliseconds),
+/// or the next one to start if none is running.
+/// Of overlapping events the latest started wins, then the highest id,
+/// and of events starting together next the highest id.
+pub fn current_event(
+    events: &HashMap<String, Event>,
+    timestamp: u64,
+    server: Server,
+) -> Option<u32> {
+    let mut running: Option<(u64, u32)> = None;
+    let mut next: Option<(u64, u32)> = None;
+    for (event_id, event) in events.iter() {
+        let event_id = match event_id.parse::<u32>() {
+            Ok(event_id) => event_id,
+            Err(_) => continue,
+        };
+        let (start_at, end_at) = match event.period(server) {
+            Some(period) => period,
+            None => continue,
+        };
+        if start_at <= timestamp && timestamp < end_at {
+            if running.map_or(true, |running| (start_at, event_id) > running) {
+                running = Some((start_at, event_id));
+            }
+        } else if start_at > timestamp
+            && next.map_or(true, |(next_start, next_id)| {
+                start_at < next_start || (start_at == next_start && event_id > next_id)
+            })
+        {
+            next = Some((start_at, event_id));
+        }
+    }
+    running.or(next).map(|(_, event_id)| event_id)
 }
 
 /// Card status from Bestdori's encode data
+#[derive(Serialize, Deserialize, Clone)]
 pub struct CardStatus {
     /// Card id
     pub id: u32,
@@ -110,6 +554,28 @@ pub struct CardStatus {
     pub skill: u8,
 }
 
+/// Song base data
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Song {
+    #[allow(dead_code)]
+    pub music_title: Vec<Value>,
+    #[allow(dead_code)]
+    pub band_id: u8,
+    /// Difficulties, keyed by "0" (easy) to "4" (special)
+    pub difficulty: HashMap<String, SongDifficulty>,
+    /// Song length in seconds
+    pub length: f64,
+    #[allow(dead_code)]
+    pub published_at: Vec<Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SongDifficulty {
+    pub play_level: u32,
+}
+
 #[derive(Deserialize)]
 pub struct SongNote {
     pub time: f64,
@@ -121,6 +587,7 @@ pub struct SongNote {
 #[serde(rename_all = "camelCase")]
 pub struct ActivateEffectType {
     pub activate_effect_value: Vec<Value>,
+    #[allow(dead_code)]
     pub activate_effect_value_type: String,
     pub activate_condition: String
 }
@@ -129,6 +596,7 @@ pub struct ActivateEffectType {
 #[serde(rename_all = "camelCase")]
 pub struct ActivationEffect {
     pub unification_activate_effect_value: Option<u32>,
+    #[allow(dead_code)]
     pub unification_activate_condition_band_id: Option<u32>,
     pub activate_effect_types: HashMap<String, ActivateEffectType>
 }
@@ -139,105 +607,167 @@ pub struct Skill {
     pub duration: Vec<f64>,
     pub activation_effect: ActivationEffect,
     #[serde(flatten)]
+    #[allow(dead_code)]
     pub extra: HashMap<String, Value>,
 }
 
-fn b(t: &str) -> u32 {
+fn b(t: &str) -> Option<u32> {
     let mut n: u32 = 0;
     let base: u32 = 64;
     let table: &'static str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz-_";
     for (cnt, ch) in t.chars().enumerate() {
-        n += match table.find(ch) {
-            Some(value) => value as u32,
-            None => {
-                println!("Invalid input!");
-                return 0;
-            }
-        } * base.pow((t.len() - cnt - 1) as u32);
+        n += table.find(ch)? as u32 * base.pow((t.len() - cnt - 1) as u32);
     }
-    n
+    Some(n)
 }
 
-pub fn decode_data(encrypted: &String) -> Vec<CardStatus> {
+/// Card status of one 5 character entry, `None` if it has characters out of the table
+fn decode_entry(entry: &str) -> Option<CardStatus> {
+    let id = b(&entry[0..2])?;
+    let level = b(&entry[2..3])? as u8;
+    let mut i = b(&entry[3..5])?;
+    let exclude = i % 2 == 1;
+    i = (i as f64 / 2.0).floor() as u32;
+    let art = (i % 2) as u8;
+    i = (i as f64 / 2.0).floor() as u32;
+    let train = (i % 2) as u8;
+    i = (i as f64 / 2.0).floor() as u32;
+    let ep = (i % 3) as u8;
+    i = (i as f64 / 3.0).floor() as u32;
+    let skill = i as u8;
+    Some(CardStatus {
+        id,
+        level,
+        exclude,
+        art,
+        train,
+        ep,
+        skill,
+    })
+}
+
+/// Decode every card of Bestdori's encoded data, 5 characters each.
+/// Entries that can't be decoded are returned apart, as they are.
+pub fn decode_data(encrypted: &str) -> (Vec<CardStatus>, Vec<String>) {
     let mut lst: Vec<CardStatus> = Vec::new();
+    let mut invalid: Vec<String> = Vec::new();
     let mut n = 0;
     while n < encrypted.len() {
-        let id = b(&encrypted[n..n + 2]);
-        n += 2;
-        let level = b(&encrypted[n..n + 1]) as u8;
-        n += 1;
-        let mut i = b(&encrypted[n..n + 2]);
-        n += 2;
-        let exclude = i % 2 == 1;
-        i = (i as f64 / 2.0).floor() as u32;
-        let art = (i % 2) as u8;
-        i = (i as f64 / 2.0).floor() as u32;
-        let train = (i % 2) as u8;
-        i = (i as f64 / 2.0).floor() as u32;
-        let ep = (i % 3) as u8;
-        i = (i as f64 / 3.0).floor() as u32;
-        let skill = i as u8;
-        lst.push(CardStatus {
-            id,
-            level,
-            exclude,
-            art,
-            train,
-            ep,
-            skill,
-        })
+        let entry = match encrypted.get(n..n + 5) {
+            Some(entry) => entry,
+            None => {
+                invalid.push(encrypted.get(n..).unwrap_or_default().to_string());
+                break;
+            }
+        };
+        match decode_entry(entry) {
+            Some(card_stat) => lst.push(card_stat),
+            None => invalid.push(entry.to_string()),
+        }
+        n += 5;
+    }
+    (lst, invalid)
+}
+
+/// Area items in the raw profile that don't belong to a band
+const AREA_ITEMS: [&str; 4] = ["Everyone", "Menu", "Plaza", "Magazine"];
+
+/// Area items that every profile should have, with how many items there are
+const REQUIRED_ITEMS: [(&str, usize); 3] = [("Menu", 4), ("Plaza", 4), ("Magazine", 3)];
+
+impl RawUserProfile {
+    /// Levels of the item, items missing from the profile are at level 0
+    fn item_levels(&self, name: &str, count: usize) -> Vec<u8> {
+        let mut levels = self.items.get(name).cloned().unwrap_or_default();
+        levels.resize(count, 0);
+        levels
+    }
+
+    /// Area items that are missing from the profile or have fewer items than they should
+    fn missing_items(&self) -> Vec<String> {
+        REQUIRED_ITEMS
+            .iter()
+            .filter(|(name, count)| self.items.get(*name).map_or(true, |it| it.len() < *count))
+            .map(|(name, _)| name.to_string())
+            .collect()
+    }
+}
+
+/// Name in lower case without spaces and symbols,
+/// so `RaiseASuilen` in the profile matches RAISE A SUILEN
+fn item_key_of(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Map item names to band ids, from bestdori's bands.json.
+/// Every language of the band name is used, see `item_key_of`.
+pub fn band_items_new(bands: &HashMap<String, Band>) -> HashMap<String, u8> {
+    let mut band_items: HashMap<String, u8> = HashMap::new();
+    for (band_id, band) in bands.iter() {
+        let band_id = match band_id.parse::<u8>() {
+            Ok(band_id) => band_id,
+            Err(_) => continue,
+        };
+        let names = band.band_name.as_array().cloned().unwrap_or_default();
+        for name in names.iter().filter_map(|name| name.as_str()) {
+            let key = item_key_of(name);
+            if !key.is_empty() {
+                band_items.insert(key, band_id);
+            }
+        }
     }
-    lst
+    band_items
 }
 
 impl UserProfile {
-    pub fn new(raw: &RawUserProfile) -> UserProfile {
-        let mut bands: HashMap<String, Vec<f64>> = HashMap::new();
+    /// `band_items` maps the profile's item names to band ids, see `band_items_new`
+    pub fn new(raw: &RawUserProfile, band_items: &HashMap<String, u8>) -> UserProfile {
+        let mut bands: HashMap<u8, Vec<f64>> = HashMap::new();
         let band_item_percentage = |v: &u8| *v as f64 / 100.0;
-        let band_rename = [
-            ("Afterglow", "Afterglow"),
-            ("Everyone", "Everyone"),
-            ("Hello, Happy World!", "HelloHappyWorld"),
-            ("Pastel＊Palettes", "PastelPalettes"),
-            ("Poppin'Party", "PoppinParty"),
-            ("Roselia", "Roselia"),
-            ("RAISE A SUILEN", "RaiseASuilen"),
-            ("Morfonica", "Morfonica"),
-            ("Everyone", "Everyone"),
-        ];
+        // Bands without items in the profile get no bonus
+        for band_id in band_items.values() {
+            bands.insert(*band_id, Vec::new());
+        }
         // Calculate band bonus that based on band's item
-        let default_bonus: Vec<u8> = vec![1, 1, 1, 1, 1, 1, 1];
-        for (band_name, band_name_profile) in band_rename.iter() {
-            bands.insert(
-                band_name.to_string(),
-                raw.items
-                    .get(*band_name_profile)
-                    .unwrap_or(&default_bonus)
-                    .iter()
-                    .map(band_item_percentage)
-                    .collect(),
-            );
+        let mut unknown_items: Vec<String> = Vec::new();
+        for (item_name, levels) in raw.items.iter() {
+            if AREA_ITEMS.contains(&item_name.as_str()) {
+                continue;
+            }
+            match band_items.get(&item_key_of(item_name)) {
+                Some(band_id) => {
+                    bands.insert(*band_id, levels.iter().map(band_item_percentage).collect());
+                }
+                None => unknown_items.push(item_name.clone()),
+            }
         }
-        // Everyone's item is different
-        bands.insert(
-            "Everyone".to_string(),
-            raw.items["Everyone"]
-                .iter()
-                .map(|v| *v as f64 * 0.25 + 1.0)
-                .collect(),
-        );
+        unknown_items.sort();
+        // Items for every band are left out, their bonus per level isn't verified yet
+        let everyone: Vec<f64> = Vec::new();
         let item_percentage = |v: &u8| *v as f64 / 100.0;
-        let mut props: HashMap<String, Vec<f64>> = HashMap::new();
-        let menu: Vec<f64> = raw.items["Menu"].iter().map(item_percentage).collect();
-        let plaza: Vec<f64> = raw.items["Plaza"].iter().map(item_percentage).collect();
-        for (i, attr) in ["powerful", "cool", "happy", "pure"].iter().enumerate() {
-            props.insert(String::from(attr.clone()), vec![menu[i], plaza[i]]);
+        let mut props: HashMap<Attribute, Vec<f64>> = HashMap::new();
+        let menu: Vec<f64> = raw
+            .item_levels("Menu", 4)
+            .iter()
+            .map(item_percentage)
+            .collect();
+        let plaza: Vec<f64> = raw
+            .item_levels("Plaza", 4)
+            .iter()
+            .map(item_percentage)
+            .collect();
+        for (i, attr) in Attribute::ALL.iter().enumerate() {
+            props.insert(*attr, vec![menu[i], plaza[i]]);
         }
         let magazine_percentage = |v: &u8| match v {
             0 => 0.0,
             _ => *v as f64 * 0.02 + 0.06,
         };
-        let magazine: Vec<f64> = raw.items["Magazine"]
+        let magazine: Vec<f64> = raw
+            .item_levels("Magazine", 3)
             .iter()
             .map(magazine_percentage)
             .collect();
@@ -246,11 +776,16 @@ impl UserProfile {
             technique: magazine[1],
             visual: magazine[2],
         };
-        let card_status = decode_data(&raw.data);
+        let (card_status, invalid_entries) = decode_data(&raw.data);
         UserProfile {
             name: raw.name.clone(),
             server: raw.server,
+            as_of: None,
             bands,
+            everyone,
+            unknown_items,
+            missing_items: raw.missing_items(),
+            invalid_entries,
             props,
             magazine,
             card_status,
@@ -258,195 +793,156 @@ impl UserProfile {
     }
 }
 
-/// Generate character and band relation
+impl Card {
+    /// Number of episodes the card has
+    pub fn episode_count(&self) -> u8 {
+        self.stat
+            .get("episodes")
+            .and_then(|it| it.as_array())
+            .map_or(0, |it| it.len() as u8)
+    }
+
+    /// Highest level of the card, trained or not
+    pub fn max_level(&self, train: u8) -> u8 {
+        match train {
+            1 => self.level_limit + TRAIN_LEVEL_BONUS,
+            _ => self.level_limit,
+        }
+    }
+
+    /// Error if the level is out of the card's levels
+    pub fn check_level(&self, level: u8, train: u8) -> Result<(), String> {
+        let max_level = self.max_level(train);
+        if level == 0 || level > max_level {
+            return Err(format!("level {} is out of 1 to {}", level, max_level));
+        }
+        Ok(())
+    }
+
+    /// The card's `stat`, parsed once
+    fn parsed_stat(&self) -> &ParsedStat {
+        self.parsed_stat.get_or_init(|| {
+            let mut parsed = ParsedStat::default();
+            for (rank, info) in self.stat.iter() {
+                match rank.as_str() {
+                    "episodes" => {
+                        parsed.episodes = serde_json::from_value(info.clone()).unwrap_or_default()
+                    }
+                    "training" => parsed.training = serde_json::from_value(info.clone()).ok(),
+                    level => {
+                        if let (Ok(level), Ok(stat)) = (
+                            level.parse::<u8>(),
+                            serde_json::from_value::<CardData>(info.clone()),
+                        ) {
+                            parsed.levels.push((level, stat));
+                        }
+                    }
+                }
+            }
+            parsed.levels.sort_by_key(|(level, _)| *level);
+            parsed
+        })
+    }
+
+    /// Stats of every level in the card's own stat
+    fn level_stats(&self) -> &[(u8, CardData)] {
+        &self.parsed_stat().levels
+    }
+
+    /// Stats of the card with its level, read episodes and training,
+    /// before area items and event bonus
+    pub fn status_stat(&self, card_stat: &CardStatus) -> CardData {
+        let mut card_data = self.level_stat(card_stat.level);
+        let parsed = self.parsed_stat();
+        let episodes = parsed.episodes.iter().take(card_stat.ep as usize);
+        // Training bonus, only for trained card
+        let training = parsed.training.iter().filter(|_| card_stat.train == 1);
+        for stat in episodes.chain(training) {
+            card_data.performance += stat.performance;
+            card_data.technique += stat.technique;
+            card_data.visual += stat.visual;
+        }
+        card_data
+    }
+
+    /// Card stats at the level. The card's own stat of the level is used when it exists,
+    /// otherwise the nearest levels it has are interpolated along the rarity's level curve.
+    /// Levels above the card's own levels, such as trained levels, follow the curve on
+    /// from its two highest levels, and levels below them are clamped.
+    pub fn level_stat(&self, level: u8) -> CardData {
+        let level_stats = self.level_stats();
+        let lower = level_stats.iter().rev().find(|(l, _)| *l <= level);
+        let upper = level_stats.iter().find(|(l, _)| *l >= level);
+        let ((low_level, low), (high_level, high)) = match (lower, upper) {
+            (Some(lower), Some(upper)) => (lower, upper),
+            (Some(_), None) if level_stats.len() >= 2 => (
+                &level_stats[level_stats.len() - 2],
+                &level_stats[level_stats.len() - 1],
+            ),
+            (Some((_, stat)), None) | (None, Some((_, stat))) => return *stat,
+            (None, None) => {
+                return CardData {
+                    performance: 0,
+                    technique: 0,
+                    visual: 0,
+                }
+            }
+        };
+        if low_level == high_level {
+            return *low;
+        }
+        // Progress between the two levels, linear if the curve doesn't cover them
+        let point = |level: u8| level_curve(self.rarity)?.get(level as usize - 1).copied();
+        let rate = match (point(*low_level), point(level), point(*high_level)) {
+            (Some(low_point), Some(point), Some(high_point)) if high_point > low_point => {
+                (point - low_point) / (high_point - low_point)
+            }
+            _ => (level - low_level) as f64 / (high_level - low_level) as f64,
+        };
+        let lerp = |low: u32, high: u32| (low as f64 + (high as f64 - low as f64) * rate) as u32;
+        CardData {
+            performance: lerp(low.performance, high.performance),
+            technique: lerp(low.technique, high.technique),
+            visual: lerp(low.visual, high.visual),
+        }
+    }
+
+    /// Card title in the server's language
+    pub fn title(&self, server: Server) -> Option<String> {
+        localized_name(&self.prefix, server)
+    }
+
+    /// Whether the card is released on the server by the evaluation date
+    pub fn released_by(&self, server: Server, as_of: Option<u64>) -> bool {
+        match self.released_at.get(server.index()).and_then(timestamp_of) {
+            Some(released_at) => as_of.map_or(true, |as_of| released_at <= as_of),
+            None => false,
+        }
+    }
+}
+
+/// Generate character id and band id relation, characters of unknown bands are left out
 pub fn character_band_new(
-    characters: HashMap<String, Character>,
-    bands: HashMap<String, Band>,
-) -> HashMap<u8, String> {
-    let mut character_band: HashMap<u8, String> = HashMap::new();
+    characters: &HashMap<String, Character>,
+    bands: &HashMap<String, Band>,
+) -> HashMap<u8, u8> {
+    let mut character_band: HashMap<u8, u8> = HashMap::new();
     for (character_id, character) in characters.iter() {
         let character_id = character_id.parse::<u8>().unwrap();
-        let band = bands.get(&character.band_id.to_string()).unwrap();
-        character_band.insert(character_id, band.band_name[1].to_string());
+        if bands.contains_key(&character.band_id.to_string()) {
+            character_band.insert(character_id, character.band_id);
+        }
     }
     character_band
 }
 
-pub fn get_level_score(curr_level: u8, rarity: u8) -> f64 {
-    let r1 = [
-        0.0,
-        0.027741577148418566,
-        0.05827079766928518,
-        0.09157023784727926,
-        0.12763157919247634,
-        0.16646335421245123,
-        0.2080467329810958,
-        0.2523725635815978,
-        0.29945970809515476,
-        0.3493549269647502,
-        0.4019829891560635,
-        0.45737145350963615,
-        0.5155589441139967,
-        0.5765070194790465,
-        0.6401879689268755,
-        0.7066399177484718,
-        0.77583438444173,
-        0.8477702087090347,
-        0.922495295719485,
-        1.0,
-    ];
-    let r2 = [
-        0.0,
-        0.017856719467337606,
-        0.036896819500198366,
-        0.05712720353262898,
-        0.0785481410852151,
-        0.10115068684022054,
-        0.12493367020010375,
-        0.14991873595264252,
-        0.17609001362625878,
-        0.20343906942389595,
-        0.23198087664087155,
-        0.26171208783900635,
-        0.2926379539012518,
-        0.324741136882865,
-        0.3580344489450513,
-        0.3925231410698117,
-        0.4281932552740394,
-        0.46505212999997836,
-        0.5031024182461973,
-        0.5423349324988558,
-        0.5827566752115649,
-        0.6243699948827242,
-        0.6671642798084607,
-        0.7111523418284023,
-        0.7563202420759938,
-        0.802684231316528,
-        0.8502340870909751,
-        0.8989700203928614,
-        0.9488922676005076,
-        1.0,
-    ];
+/// Level curves of every rarity, from 0 at level 1 to 1 at the highest level
+const LEVEL_CURVES: &str = include_str!("level_curves.json");
 
-    let r3 = [
-        0.0,
-        0.006579811100923505,
-        0.013490376941789076,
-        0.02072357033736649,
-        0.0282857469218734,
-        0.036174782566627796,
-        0.044399557457405224,
-        0.0529520377427399,
-        0.06182744102603252,
-        0.07104081341142438,
-        0.0805784925749624,
-        0.09044396348716642,
-        0.1006360985970335,
-        0.11115826738840272,
-        0.12201077801526514,
-        0.1331914612552324,
-        0.14469696632024545,
-        0.1565321016810991,
-        0.16870064899154105,
-        0.18119441263664268,
-        0.19401618052690653,
-        0.20716987792842392,
-        0.220646377954231,
-        0.23445670502582086,
-        0.24859157352745323,
-        0.26305982052078125,
-        0.2778572809267125,
-        0.29297495807935886,
-        0.30842764497977343,
-        0.3242045972904203,
-        0.3403108556813293,
-        0.3567490256479929,
-        0.37351842656938367,
-        0.3906106859938102,
-        0.4080321560667454,
-        0.4257874104768857,
-        0.4438663146273812,
-        0.4622730945351113,
-        0.48101099472751,
-        0.5000713733964425,
-        0.5277508312304107,
-        0.5603880999928903,
-        0.5979841991476733,
-        0.6405413574510139,
-        0.6880557418260439,
-        0.7405268767404022,
-        0.7979648036251932,
-        0.8603540332444733,
-        0.9276952510130176,
-        1.0,
-    ];
-    let r4 = [
-        0.0,
-        0.0052137940761305835,
-        0.010626772033633004,
-        0.01625140619739073,
-        0.02208300765878985,
-        0.028130123879720123,
-        0.03438591969492338,
-        0.040845165089679725,
-        0.04751431401768039,
-        0.05438920952360014,
-        0.06146926738626297,
-        0.06876102000541633,
-        0.07625624123106317,
-        0.08396613839157453,
-        0.09187956441780568,
-        0.1000002838288349,
-        0.1083369509623417,
-        0.11687849700242568,
-        0.12562643419463135,
-        0.13458349890179105,
-        0.14375149230097023,
-        0.1531220343111895,
-        0.1627012369922088,
-        0.1724891364692424,
-        0.18248307296777722,
-        0.19268953004894984,
-        0.2031038118277759,
-        0.2137266946705706,
-        0.22455873405686588,
-        0.23560016086505522,
-        0.246845117069297,
-        0.2583022752066268,
-        0.26996585295356473,
-        0.2818368100569856,
-        0.29391689352667993,
-        0.3062034732003648,
-        0.31870850250918314,
-        0.3314136656714306,
-        0.34432706913752,
-        0.35744699511750455,
-        0.37077464809342076,
-        0.3843126970348029,
-        0.39805844303293897,
-        0.41201000405281624,
-        0.4261726805173379,
-        0.440542342723313,
-        0.4551202726012628,
-        0.46990761662317243,
-        0.48490456025578976,
-        0.5001066997170803,
-        0.5277872974717714,
-        0.5604260864848988,
-        0.5980193982337021,
-        0.640573453684307,
-        0.6880874211513187,
-        0.7405574835992293,
-        0.7979798883017865,
-        0.8603639584557542,
-        0.927706300412736,
-        1.0,
-    ];
-    match rarity {
-        1 => *r1.get((curr_level - 1) as usize).unwrap_or(&1.0 as &f64),
-        2 => *r2.get((curr_level - 1) as usize).unwrap_or(&1.0 as &f64),
-        3 => *r3.get((curr_level - 1) as usize).unwrap_or(&1.0 as &f64),
-        4 => *r4.get((curr_level - 1) as usize).unwrap_or(&1.0 as &f64),
-        _ => 1.0,
-    }
+/// Level curve of the rarity, `None` for unknown rarities
+fn level_curve(rarity: u8) -> Option<&'static Vec<f64>> {
+    static CURVES: OnceLock<HashMap<String, Vec<f64>>> = OnceLock::new();
+    CURVES
+        .get_or_init(|| serde_json::from_str(LEVEL_CURVES).unwrap())
+        .get(&rarity.to_string())
 }
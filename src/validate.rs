@@ -0,0 +1,154 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::user_data::*;
+
+/// A card whose value is out of its range
+#[derive(Serialize)]
+pub struct ValueIssue {
+    card_id: u32,
+    value: u8,
+    /// Highest value the card can have
+    max: u8,
+}
+
+/// Problems found in the profile, cards and items in them are left out or taken as level 0
+/// by the optimizer
+#[derive(Serialize, Default)]
+pub struct ProfileReport {
+    /// Cards that are not in cards.json
+    unknown_cards: Vec<u32>,
+    /// Cards that are not released on the profile's server by the evaluation date
+    unreleased_cards: Vec<u32>,
+    /// Cards whose level is 0 or above the level limit
+    invalid_levels: Vec<ValueIssue>,
+    /// Cards whose skill level is above the skill's levels
+    invalid_skill_levels: Vec<ValueIssue>,
+    /// Area items missing from the profile
+    missing_items: Vec<String>,
+    /// Item names that are neither a band nor a known area item
+    unknown_items: Vec<String>,
+    /// Card entries of the profile's data that can't be decoded
+    invalid_entries: Vec<String>,
+}
+
+/// Check every card and item in the profile before optimizing
+pub fn validate_profile(
+    cards: &HashMap<String, Card>,
+    user_profile: &UserProfile,
+    skills: &HashMap<String, Skill>,
+) -> ProfileReport {
+    let mut report = ProfileReport {
+        missing_items: user_profile.missing_items.clone(),
+        unknown_items: user_profile.unknown_items.clone(),
+        invalid_entries: user_profile.invalid_entries.clone(),
+        ..Default::default()
+    };
+    for card_stat in user_profile.card_status.iter() {
+        let card = match cards.get(&card_stat.id.to_string()) {
+            Some(card) => card,
+            None => {
+                report.unknown_cards.push(card_stat.id);
+                continue;
+            }
+        };
+        if !card.released_by(user_profile.server, user_profile.as_of) {
+            report.unreleased_cards.push(card_stat.id);
+        }
+        if card.check_level(card_stat.level, card_stat.train).is_err() {
+            report.invalid_levels.push(ValueIssue {
+                card_id: card_stat.id,
+                value: card_stat.level,
+                max: card.max_level(card_stat.train),
+            });
+        }
+        // Skill levels start from 0
+        if let Some(skill) = skills.get(&card.skill_id.to_string()) {
+            let max_skill = skill.duration.len().saturating_sub(1) as u8;
+            if card_stat.skill > max_skill {
+                report.invalid_skill_levels.push(ValueIssue {
+                    card_id: card_stat.id,
+                    value: card_stat.skill,
+                    max: max_skill,
+                });
+            }
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_json::*;
+    use crate::{calc_max_score, EventType};
+
+    #[test]
+    fn validate_test() {
+        let Fixture {
+            cards: all_cards,
+            mut raw_user_profile,
+            user_profile,
+            band_items,
+            skills,
+            ..
+        } = read_fixture();
+        let report = validate_profile(&all_cards, &user_profile, &skills);
+        assert!(report.unknown_cards.is_empty());
+        assert!(report.missing_items.is_empty());
+        assert!(report.invalid_entries.is_empty());
+        // Broken cards and items are reported instead of panicking
+        raw_user_profile.items.remove("Plaza");
+        let decoded = user_profile.card_status.len();
+        raw_user_profile.data.push_str("A!B0sA");
+        let mut user_profile = UserProfile::new(&raw_user_profile, &band_items);
+        assert!(user_profile.props.values().all(|it| it[1] == 0.0));
+        let mut card_stat = user_profile.card_status[0].clone();
+        card_stat.level = 0;
+        card_stat.skill = 9;
+        user_profile.card_status.push(card_stat.clone());
+        card_stat.id = u32::MAX;
+        user_profile.card_status.push(card_stat);
+        let report = validate_profile(&all_cards, &user_profile, &skills);
+        assert_eq!(report.unknown_cards, vec![u32::MAX]);
+        assert_eq!(report.missing_items, vec![String::from("Plaza")]);
+        assert_eq!(
+            report.invalid_entries,
+            vec![String::from("A!B0s"), String::from("A")]
+        );
+        assert_eq!(user_profile.card_status.len(), decoded + 2);
+        assert!(report.invalid_levels.iter().any(|it| it.value == 0));
+        assert!(report.invalid_skill_levels.iter().any(|it| it.value == 9));
+    }
+
+    #[test]
+    fn invalid_skill_level_test() {
+        let Fixture {
+            cards: all_cards,
+            mut user_profile,
+            character_band,
+            song_notes,
+            skills,
+            ..
+        } = read_fixture();
+        let event_bonus = summer_vacation_bonus();
+        let mut score_with_skill = |skill: u8| {
+            for card_stat in user_profile.card_status.iter_mut() {
+                card_stat.skill = skill;
+            }
+            calc_max_score(
+                &all_cards,
+                &user_profile,
+                &event_bonus,
+                &character_band,
+                &song_notes,
+                26,
+                &skills,
+                EventType::VS,
+            )
+            .score
+        };
+        // Skill levels the skills don't have are taken as level 0 instead of panicking
+        assert_eq!(score_with_skill(9), score_with_skill(0));
+    }
+}